@@ -0,0 +1,197 @@
+use crate::*;
+
+#[derive(BorshSerialize, BorshDeserialize)]
+pub struct Approval {
+    // remaining duration the spender is allowed to move, unlimited if None
+    pub max_duration_ms: Option<u128>,
+    // timestamp in ms after which the approval is no longer valid
+    pub expires_at: Option<u128>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct ApprovalOutput {
+    pub spender_id: AccountId,
+    pub max_duration_ms: Option<U128>,
+    pub expires_at: Option<U128>,
+}
+
+impl ApprovalOutput {
+    fn new(spender_id: AccountId, approval: &Approval) -> Self {
+        ApprovalOutput {
+            spender_id,
+            max_duration_ms: approval.max_duration_ms.map(U128::from),
+            expires_at: approval.expires_at.map(U128::from),
+        }
+    }
+}
+
+#[near_bindgen]
+impl SocialPremium {
+    #[payable]
+    pub fn approve(
+        &mut self,
+        name: SubscriptionName,
+        spender_id: AccountId,
+        max_duration_ms: Option<U128>,
+        expires_at: Option<U128>,
+    ) {
+        let initial_storage = env::storage_usage();
+        let owner_id = env::predecessor_account_id();
+
+        self.assert_subscription(&name);
+        assert_ne!(owner_id, spender_id, "ERR_SELF_APPROVAL_NOT_ALLOWED");
+
+        if let Some(expires_at) = expires_at {
+            let now: u128 = env::block_timestamp_ms().into();
+            assert!(expires_at.0 > now, "ERR_APPROVAL_EXPIRED");
+        }
+
+        let key = (owner_id, name);
+        let mut approvals = self.approvals.get(&key).unwrap_or_default();
+        approvals.insert(
+            spender_id,
+            Approval {
+                max_duration_ms: max_duration_ms.map(|duration| duration.0),
+                expires_at: expires_at.map(|timestamp| timestamp.0),
+            },
+        );
+        self.approvals.insert(&key, &approvals);
+
+        charge_storage_deposit(initial_storage);
+    }
+
+    #[payable]
+    pub fn revoke(&mut self, name: SubscriptionName, spender_id: AccountId) {
+        assert_eq!(env::attached_deposit(), ONE_YOCTO, "ERR_ONE_YOCTO_REQUIRED");
+
+        let initial_storage = env::storage_usage();
+        let owner_id = env::predecessor_account_id();
+
+        self.internal_remove_approval(&owner_id, &name, &spender_id);
+
+        refund_released_storage(initial_storage, owner_id);
+    }
+
+    #[payable]
+    pub fn transfer_from(
+        &mut self,
+        name: SubscriptionName,
+        owner_id: AccountId,
+        receiver_id: AccountId,
+        duration_ms: Option<U128>,
+    ) -> Promise {
+        assert_eq!(env::attached_deposit(), ONE_YOCTO, "ERR_ONE_YOCTO_REQUIRED");
+
+        let spender_id = env::predecessor_account_id();
+
+        let approval = self
+            .internal_get_approval(&owner_id, &name, &spender_id)
+            .expect("ERR_NO_APPROVAL");
+        // limited approvals can only move an explicit duration
+        if let Some(max_duration_ms) = approval.max_duration_ms {
+            let duration_ms = duration_ms.expect("ERR_DURATION_REQUIRED");
+            assert!(
+                duration_ms.0 <= max_duration_ms,
+                "ERR_APPROVAL_DURATION_EXCEEDED"
+            );
+        }
+
+        self.internal_transfer(name, owner_id, receiver_id, duration_ms, Some(spender_id))
+    }
+
+    pub fn get_approval(
+        &self,
+        owner_id: AccountId,
+        name: SubscriptionName,
+        spender_id: AccountId,
+    ) -> Option<ApprovalOutput> {
+        self.internal_get_approval(&owner_id, &name, &spender_id)
+            .map(|approval| ApprovalOutput::new(spender_id, &approval))
+    }
+
+    pub fn get_approvals(
+        &self,
+        owner_id: AccountId,
+        name: SubscriptionName,
+    ) -> Vec<ApprovalOutput> {
+        self.approvals
+            .get(&(owner_id, name))
+            .unwrap_or_default()
+            .iter()
+            .map(|(spender_id, approval)| ApprovalOutput::new(spender_id.clone(), approval))
+            .collect()
+    }
+}
+
+impl SocialPremium {
+    // returns a valid (not expired) approval
+    pub(crate) fn internal_get_approval(
+        &self,
+        owner_id: &AccountId,
+        name: &SubscriptionName,
+        spender_id: &AccountId,
+    ) -> Option<Approval> {
+        let now: u128 = env::block_timestamp_ms().into();
+
+        self.approvals
+            .get(&(owner_id.clone(), name.clone()))
+            .and_then(|mut approvals| approvals.remove(spender_id))
+            .filter(|approval| approval.expires_at.unwrap_or(u128::MAX) > now)
+    }
+
+    pub(crate) fn internal_remove_approval(
+        &mut self,
+        owner_id: &AccountId,
+        name: &SubscriptionName,
+        spender_id: &AccountId,
+    ) {
+        let key = (owner_id.clone(), name.clone());
+        let mut approvals = self.approvals.get(&key).expect("ERR_NO_APPROVAL");
+        approvals.remove(spender_id).expect("ERR_NO_APPROVAL");
+
+        if approvals.is_empty() {
+            self.approvals.remove(&key);
+        } else {
+            self.approvals.insert(&key, &approvals);
+        }
+    }
+
+    // decreases the allowance of the approval by the transferred duration
+    pub(crate) fn internal_use_approval(
+        &mut self,
+        owner_id: &AccountId,
+        name: &SubscriptionName,
+        spender_id: &AccountId,
+        duration_ms: u128,
+    ) {
+        let approval = self
+            .internal_get_approval(owner_id, name, spender_id)
+            .expect("ERR_NO_APPROVAL");
+
+        if let Some(max_duration_ms) = approval.max_duration_ms {
+            assert!(
+                duration_ms <= max_duration_ms,
+                "ERR_APPROVAL_DURATION_EXCEEDED"
+            );
+
+            if duration_ms == max_duration_ms {
+                // the storage of the consumed approval is returned to the owner
+                let initial_storage = env::storage_usage();
+                self.internal_remove_approval(owner_id, name, spender_id);
+                refund_released_storage(initial_storage, owner_id.clone());
+            } else {
+                let key = (owner_id.clone(), name.clone());
+                let mut approvals = self.approvals.get(&key).expect("ERR_NO_APPROVAL");
+                approvals.insert(
+                    spender_id.clone(),
+                    Approval {
+                        max_duration_ms: Some(max_duration_ms - duration_ms),
+                        expires_at: approval.expires_at,
+                    },
+                );
+                self.approvals.insert(&key, &approvals);
+            }
+        }
+    }
+}
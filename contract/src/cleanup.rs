@@ -0,0 +1,264 @@
+use crate::*;
+
+use near_sdk::is_promise_success;
+use near_sdk::serde_json::{Map, Value};
+
+pub const MAX_CLEANUP_BATCH_SIZE: u64 = 50;
+pub const GAS_FOR_AFTER_CLEANUP: Gas = Gas(Gas::ONE_TERA.0 * 50);
+
+#[derive(BorshSerialize, BorshDeserialize)]
+pub struct CleanupConfig {
+    // time after the expiry when the badge can be removed
+    pub grace_ms: u128,
+    // paid to the caller for every removed badge
+    pub keeper_fee: Balance,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct CleanupConfigInput {
+    pub grace_ms: U128,
+    pub keeper_fee: U128,
+}
+
+impl Default for CleanupConfig {
+    fn default() -> Self {
+        CleanupConfig {
+            grace_ms: MONTH_IN_MS,
+            keeper_fee: 0,
+        }
+    }
+}
+
+#[near_bindgen]
+impl SocialPremium {
+    pub fn set_cleanup_config(&mut self, cleanup_config: CleanupConfigInput) {
+        self.assert_owner();
+        self.cleanup_config = CleanupConfig {
+            grace_ms: cleanup_config.grace_ms.0,
+            keeper_fee: cleanup_config.keeper_fee.0,
+        };
+    }
+
+    pub fn get_cleanup_config(&self) -> CleanupConfigInput {
+        CleanupConfigInput {
+            grace_ms: U128::from(self.cleanup_config.grace_ms),
+            keeper_fee: U128::from(self.cleanup_config.keeper_fee),
+        }
+    }
+
+    // removes the badges expired longer than the grace period from the SocialDB,
+    // returns the number of badges submitted for removal
+    pub fn cleanup_expired(&mut self, name: SubscriptionName, limit: Option<u64>) -> u64 {
+        let subscription = self.internal_get_subscription(&name);
+
+        // holders in the grace period of the subscription are never removed
+        let now: u128 = env::block_timestamp_ms().into();
+        let expired_before = now.saturating_sub(std::cmp::max(
+            self.cleanup_config.grace_ms,
+            subscription.grace_period_ms,
+        ));
+        let limit = std::cmp::min(
+            limit.unwrap_or(MAX_CLEANUP_BATCH_SIZE),
+            MAX_CLEANUP_BATCH_SIZE,
+        );
+
+        // holders with an operation in progress are left for the next cleanup
+        let expired_holders: Vec<HolderOutput> = if expired_before > 0 {
+            self.get_holders_by_expiry(
+                name.clone(),
+                U128::from(0),
+                U128::from(expired_before),
+                None,
+                Some(limit),
+            )
+            .into_iter()
+            .filter(|holder| !self.is_account_locked(&holder.account_id))
+            .collect()
+        } else {
+            vec![]
+        };
+        assert!(!expired_holders.is_empty(), "ERR_NOTHING_TO_CLEANUP");
+
+        let mut receiver_data: Map<String, Value> = Map::new();
+        let mut accounts: Vec<AccountId> = vec![];
+
+        for holder in &expired_holders {
+            self.lock_account(&holder.account_id);
+
+            receiver_data.insert(holder.account_id.to_string(), Value::Null);
+            accounts.push(holder.account_id.clone());
+        }
+
+        let submitted = accounts.len() as u64;
+
+        let mut data: Map<String, Value> = Map::new();
+        data.insert(
+            SOCIAL_PREMIUM_ACCOUNT_ID.to_string(),
+            Value::Object(get_badge_accounts_data(&name, receiver_data)),
+        );
+
+        // removing data releases storage, so no deposit is attached
+        ext_social::ext(AccountId::new_unchecked(SOCIAL_DB_ACCOUNT_ID.to_string()))
+            .with_static_gas(GAS_FOR_SOCIAL_SET)
+            .set(
+                Value::Object(data),
+                SetOptions {
+                    refund_unused_deposit: true,
+                },
+            )
+            .then(
+                ext_self::ext(env::current_account_id())
+                    .with_static_gas(GAS_FOR_AFTER_CLEANUP)
+                    .on_expired_badges_removed(
+                        name,
+                        expired_holders,
+                        env::predecessor_account_id(),
+                    ),
+            )
+            .then(
+                ext_self::ext(env::current_account_id())
+                    .with_static_gas(GAS_FOR_UNLOCK)
+                    .unlock_accounts(accounts),
+            );
+
+        submitted
+    }
+
+    // the holders leave the indexes and the keeper is paid only once the badges are removed,
+    // returns the number of removed badges
+    #[private]
+    pub fn on_expired_badges_removed(
+        &mut self,
+        subscription_name: SubscriptionName,
+        holders: Vec<HolderOutput>,
+        keeper_id: AccountId,
+    ) -> u64 {
+        if !is_promise_success() {
+            log!("Expired badges of {} were not removed", subscription_name);
+            return 0;
+        }
+
+        let initial_storage = env::storage_usage();
+
+        let mut expiry_index = self
+            .expiry_index
+            .get(&subscription_name)
+            .expect("ERR_NO_DATA");
+        let mut subscription_holders = self.subscription_holders.get(&subscription_name);
+        let mut removed: u64 = 0;
+        let mut paid_removed: u64 = 0;
+
+        for holder in holders {
+            let key = (subscription_name.clone(), holder.account_id.clone());
+            expiry_index.remove(&(holder.expires_at.0, holder.account_id.clone()));
+            self.holder_expiries.remove(&key);
+            self.former_holders.insert(&key);
+            self.revoked_holders.remove(&key);
+            if self.paid_holders.remove(&key) {
+                paid_removed += 1;
+            }
+            if let Some(subscription_holders) = subscription_holders.as_mut() {
+                subscription_holders.remove(&holder.account_id);
+            }
+            removed += 1;
+        }
+
+        self.expiry_index.insert(&subscription_name, &expiry_index);
+        if let Some(subscription_holders) = subscription_holders {
+            self.subscription_holders
+                .insert(&subscription_name, &subscription_holders);
+        }
+
+        log!(
+            "Expired badges of {} removed: {}",
+            subscription_name,
+            removed
+        );
+
+        // the keeper fee is paid only for the badges of paid purchases, from the available
+        // treasury and up to the cost of the storage released by the cleanup
+        let keeper_fee = std::cmp::min(
+            std::cmp::min(
+                self.cleanup_config.keeper_fee * Balance::from(paid_removed),
+                Balance::from(initial_storage.saturating_sub(env::storage_usage()))
+                    * env::storage_byte_cost(),
+            ),
+            self.ledger.get_available(),
+        );
+        if keeper_fee > 0 {
+            self.internal_post_ledger_entry(
+                LedgerAccount::KeeperFees,
+                LedgerAccount::Available,
+                keeper_fee,
+            );
+            Promise::new(keeper_id).transfer(keeper_fee);
+        }
+
+        removed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::*;
+    use near_sdk::PromiseResult;
+
+    const KEEPER_FEE: Balance = ONE_NEAR / 1_000;
+
+    // the badge of alice is expired longer than the cleanup grace period
+    fn setup_cleanup() -> (SocialPremium, Vec<HolderOutput>, u128) {
+        let mut contract = setup_contract(None);
+        contract.set_cleanup_config(CleanupConfigInput {
+            grace_ms: U128::from(MONTH_IN_MS),
+            keeper_fee: U128::from(KEEPER_FEE),
+        });
+        purchase(&mut contract, alice(), ONE_NEAR, None, NOW_MS);
+
+        let now_ms = NOW_MS + 2 * MONTH_IN_MS + 1;
+        set_context(carol(), now_ms, 0);
+        assert_eq!(contract.cleanup_expired(NAME.to_string(), None), 1);
+
+        let holders = vec![HolderOutput {
+            account_id: alice(),
+            expires_at: U128::from(NOW_MS + MONTH_IN_MS),
+        }];
+        (contract, holders, now_ms)
+    }
+
+    #[test]
+    fn test_failed_cleanup_keeps_indexes() {
+        let (mut contract, holders, now_ms) = setup_cleanup();
+        assert!(contract.get_expiry(alice(), NAME.to_string()).is_some());
+
+        set_callback_context(now_ms, PromiseResult::Failed);
+        assert_eq!(
+            contract.on_expired_badges_removed(NAME.to_string(), holders, carol()),
+            0
+        );
+
+        assert!(contract.get_expiry(alice(), NAME.to_string()).is_some());
+        assert!(!contract
+            .former_holders
+            .contains(&(NAME.to_string(), alice())));
+        assert_eq!(contract.get_ledger().keeper_fees.0, 0);
+    }
+
+    #[test]
+    fn test_cleanup_removes_indexes_and_pays_keeper() {
+        let (mut contract, holders, now_ms) = setup_cleanup();
+
+        set_callback_context(now_ms, PromiseResult::Successful(vec![]));
+        assert_eq!(
+            contract.on_expired_badges_removed(NAME.to_string(), holders, carol()),
+            1
+        );
+
+        assert!(contract.get_expiry(alice(), NAME.to_string()).is_none());
+        assert!(contract
+            .former_holders
+            .contains(&(NAME.to_string(), alice())));
+        assert_eq!(contract.get_ledger().keeper_fees.0, KEEPER_FEE);
+    }
+}
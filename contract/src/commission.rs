@@ -0,0 +1,186 @@
+use crate::*;
+
+#[derive(BorshSerialize, BorshDeserialize)]
+pub struct RenewalCommission {
+    // time since the first referred purchase of the receiver
+    pub after_ms: u128,
+    pub fee: FeeFraction,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct RenewalCommissionInput {
+    pub after_ms: U128,
+    pub fee: FeeFraction,
+}
+
+#[derive(BorshSerialize, BorshDeserialize)]
+pub enum CommissionRule {
+    Partner,
+    Renewal { after_ms: u128 },
+    Premium,
+    Regular,
+    Rejected { reason: String },
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub enum CommissionRuleOutput {
+    Partner,
+    Renewal { after_ms: U128 },
+    Premium,
+    Regular,
+    Rejected { reason: String },
+}
+
+#[derive(BorshSerialize, BorshDeserialize)]
+pub struct ReferralCommission {
+    pub referral_account_id: ReferralAccountId,
+    pub receiver_id: AccountId,
+    pub amount: Balance,
+    pub first_purchase_at: u128,
+    pub rule: CommissionRule,
+    pub fee: Option<FeeFraction>,
+    pub reward: Balance,
+    pub second_level_referral_account_id: Option<ReferralAccountId>,
+    pub second_level_reward: Balance,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct ReferralCommissionOutput {
+    pub referral_account_id: ReferralAccountId,
+    pub receiver_id: AccountId,
+    pub amount: U128,
+    pub first_purchase_at: U128,
+    pub rule: CommissionRuleOutput,
+    pub fee: Option<FeeFraction>,
+    pub reward: U128,
+    pub second_level_referral_account_id: Option<ReferralAccountId>,
+    pub second_level_reward: U128,
+}
+
+impl From<CommissionRule> for CommissionRuleOutput {
+    fn from(rule: CommissionRule) -> Self {
+        match rule {
+            CommissionRule::Partner => CommissionRuleOutput::Partner,
+            CommissionRule::Renewal { after_ms } => CommissionRuleOutput::Renewal {
+                after_ms: U128::from(after_ms),
+            },
+            CommissionRule::Premium => CommissionRuleOutput::Premium,
+            CommissionRule::Regular => CommissionRuleOutput::Regular,
+            CommissionRule::Rejected { reason } => CommissionRuleOutput::Rejected { reason },
+        }
+    }
+}
+
+impl From<ReferralCommission> for ReferralCommissionOutput {
+    fn from(commission: ReferralCommission) -> Self {
+        ReferralCommissionOutput {
+            referral_account_id: commission.referral_account_id,
+            receiver_id: commission.receiver_id,
+            amount: U128::from(commission.amount),
+            first_purchase_at: U128::from(commission.first_purchase_at),
+            rule: commission.rule.into(),
+            fee: commission.fee,
+            reward: U128::from(commission.reward),
+            second_level_referral_account_id: commission.second_level_referral_account_id,
+            second_level_reward: U128::from(commission.second_level_reward),
+        }
+    }
+}
+
+#[near_bindgen]
+impl SocialPremium {
+    // rules replace the regular and premium fees, the rule with the largest passed after_ms applies
+    pub fn set_renewal_commissions(&mut self, renewal_commissions: Vec<RenewalCommissionInput>) {
+        self.assert_owner();
+
+        for renewal_commission in &renewal_commissions {
+            renewal_commission.fee.assert_valid();
+        }
+
+        self.renewal_commissions = renewal_commissions
+            .into_iter()
+            .map(|renewal_commission| RenewalCommission {
+                after_ms: renewal_commission.after_ms.0,
+                fee: renewal_commission.fee,
+            })
+            .collect();
+        self.assert_referral_fees_valid();
+    }
+
+    pub fn get_renewal_commissions(&self) -> Vec<RenewalCommissionInput> {
+        self.renewal_commissions
+            .iter()
+            .map(|renewal_commission| RenewalCommissionInput {
+                after_ms: U128::from(renewal_commission.after_ms),
+                fee: renewal_commission.fee.clone(),
+            })
+            .collect()
+    }
+
+    pub fn get_first_referred_purchase(&self, account_id: AccountId) -> Option<U128> {
+        self.first_referred_purchases
+            .get(&account_id)
+            .map(U128::from)
+    }
+
+    pub fn get_referral_commission(&self, operation_id: u64) -> Option<ReferralCommissionOutput> {
+        self.referral_commissions
+            .get(&operation_id)
+            .map(|commission| commission.into())
+    }
+}
+
+impl SocialPremium {
+    // returns the time of the first referred purchase of the receiver
+    pub(crate) fn internal_touch_first_referred_purchase(
+        &mut self,
+        receiver_id: &AccountId,
+    ) -> u128 {
+        self.first_referred_purchases
+            .get(receiver_id)
+            .unwrap_or_else(|| {
+                let now: u128 = env::block_timestamp_ms().into();
+                self.first_referred_purchases.insert(receiver_id, &now);
+                now
+            })
+    }
+
+    // partner fees take precedence over the renewal commissions, then premium and regular fees
+    pub(crate) fn internal_get_commission_rule(
+        &mut self,
+        referral_account_id: &ReferralAccountId,
+        amount: Balance,
+        referral_is_premium: bool,
+        first_purchase_at: u128,
+    ) -> (CommissionRule, FeeFraction) {
+        if let Some(partner_fee) = self.internal_use_partner_fee(referral_account_id, amount) {
+            return (CommissionRule::Partner, partner_fee);
+        }
+
+        let now: u128 = env::block_timestamp_ms().into();
+        let purchase_age_ms = now.saturating_sub(first_purchase_at);
+
+        if let Some(renewal_commission) = self
+            .renewal_commissions
+            .iter()
+            .filter(|renewal_commission| renewal_commission.after_ms <= purchase_age_ms)
+            .max_by_key(|renewal_commission| renewal_commission.after_ms)
+        {
+            return (
+                CommissionRule::Renewal {
+                    after_ms: renewal_commission.after_ms,
+                },
+                renewal_commission.fee.clone(),
+            );
+        }
+
+        if referral_is_premium {
+            (CommissionRule::Premium, self.premium_referral_fee.clone())
+        } else {
+            (CommissionRule::Regular, self.referral_fee.clone())
+        }
+    }
+}
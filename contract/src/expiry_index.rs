@@ -0,0 +1,111 @@
+use crate::*;
+
+use std::ops::Bound;
+
+type ExpiryKey = (u128, AccountId);
+
+#[near_bindgen]
+impl SocialPremium {
+    // holders expiring in [from_expires_at, to_expires_at) ordered by expiry,
+    // the next page starts after the expiry and account of the last returned holder
+    pub fn get_holders_by_expiry(
+        &self,
+        name: SubscriptionName,
+        from_expires_at: U128,
+        to_expires_at: U128,
+        from_account_id: Option<AccountId>,
+        limit: Option<u64>,
+    ) -> Vec<HolderOutput> {
+        assert!(
+            from_expires_at.0 < to_expires_at.0,
+            "ERR_INVALID_EXPIRY_RANGE"
+        );
+
+        let expiry_index = if let Some(expiry_index) = self.expiry_index.get(&name) {
+            expiry_index
+        } else {
+            return vec![];
+        };
+
+        let from = if let Some(from_account_id) = from_account_id {
+            Bound::Excluded((from_expires_at.0, from_account_id))
+        } else {
+            Bound::Included(get_min_expiry_key(from_expires_at.0))
+        };
+
+        expiry_index
+            .range((from, Bound::Excluded(get_min_expiry_key(to_expires_at.0))))
+            .take(limit.unwrap_or(DEFAULT_PAGINATION_LIMIT) as usize)
+            .map(|((expires_at, account_id), _)| HolderOutput {
+                account_id,
+                expires_at: U128::from(expires_at),
+            })
+            .collect()
+    }
+
+    pub fn get_expiring_holders(
+        &self,
+        name: SubscriptionName,
+        within_ms: U128,
+        limit: Option<u64>,
+    ) -> Vec<HolderOutput> {
+        let now: u128 = env::block_timestamp_ms().into();
+        self.get_holders_by_expiry(
+            name,
+            U128::from(now + 1),
+            U128::from(now + 1 + within_ms.0),
+            None,
+            limit,
+        )
+    }
+
+    pub fn get_expired_holders(
+        &self,
+        name: SubscriptionName,
+        expired_for_ms: U128,
+        limit: Option<u64>,
+    ) -> Vec<HolderOutput> {
+        let now: u128 = env::block_timestamp_ms().into();
+        self.get_holders_by_expiry(
+            name,
+            U128::from(0),
+            U128::from(now.saturating_sub(expired_for_ms.0) + 1),
+            None,
+            limit,
+        )
+    }
+}
+
+impl SocialPremium {
+    // moves the holders from their previous expiries to the new ones
+    pub(crate) fn internal_update_expiry_index(
+        &mut self,
+        subscription_name: &SubscriptionName,
+        subscriptions: &[SubscriptionData],
+        prev_expiries: &[Option<u128>],
+    ) {
+        let mut expiry_index = self.expiry_index.get(subscription_name).unwrap_or_else(|| {
+            TreeMap::new(StorageKey::ExpiryIndexPerSubscription {
+                subscription_hash: env::sha256_array(subscription_name.as_bytes()),
+            })
+        });
+
+        for (subscription, prev_expires_at) in subscriptions.iter().zip(prev_expiries) {
+            if let Some(prev_expires_at) = prev_expires_at {
+                expiry_index.remove(&(*prev_expires_at, subscription.receiver_id.clone()));
+            }
+
+            expiry_index.insert(
+                &(subscription.timestamp, subscription.receiver_id.clone()),
+                &(),
+            );
+        }
+
+        self.expiry_index.insert(subscription_name, &expiry_index);
+    }
+}
+
+// the smallest valid account id sorts before any other account with the same expiry
+fn get_min_expiry_key(expires_at: u128) -> ExpiryKey {
+    (expires_at, AccountId::new_unchecked("0-0".to_string()))
+}
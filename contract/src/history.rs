@@ -0,0 +1,123 @@
+use crate::*;
+
+// older receipts are dropped once the account history is full
+pub const MAX_HISTORY_SIZE: usize = 50;
+
+#[derive(BorshSerialize, BorshDeserialize, Serialize, Deserialize, Clone, Copy)]
+#[serde(crate = "near_sdk::serde")]
+pub enum HistoryKind {
+    Purchase,
+    // purchased by another account or started as a trial
+    Grant,
+    TransferIn,
+    TransferOut,
+    Refund,
+}
+
+#[derive(BorshSerialize, BorshDeserialize)]
+pub struct HistoryReceipt {
+    pub operation_id: u64,
+    pub kind: HistoryKind,
+    pub subscription_name: SubscriptionName,
+    pub amount: Balance,
+    // time added to the subscription, or removed for transfers out and refunds
+    pub duration_ms: u128,
+    pub expires_at: u128,
+    pub referral_account_id: Option<ReferralAccountId>,
+    // payer of grants, receiver of purchases for other accounts, other side of transfers
+    pub counterparty_id: Option<AccountId>,
+    pub timestamp: u128,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct HistoryReceiptOutput {
+    pub operation_id: u64,
+    pub kind: HistoryKind,
+    pub subscription_name: SubscriptionName,
+    pub amount: U128,
+    pub duration_ms: U128,
+    pub expires_at: U128,
+    pub referral_account_id: Option<ReferralAccountId>,
+    pub counterparty_id: Option<AccountId>,
+    pub timestamp: U128,
+}
+
+impl From<HistoryReceipt> for HistoryReceiptOutput {
+    fn from(receipt: HistoryReceipt) -> Self {
+        HistoryReceiptOutput {
+            operation_id: receipt.operation_id,
+            kind: receipt.kind,
+            subscription_name: receipt.subscription_name,
+            amount: U128::from(receipt.amount),
+            duration_ms: U128::from(receipt.duration_ms),
+            expires_at: U128::from(receipt.expires_at),
+            referral_account_id: receipt.referral_account_id,
+            counterparty_id: receipt.counterparty_id,
+            timestamp: U128::from(receipt.timestamp),
+        }
+    }
+}
+
+#[near_bindgen]
+impl SocialPremium {
+    // returns the receipts of the account starting from the latest one
+    pub fn get_history(
+        &self,
+        account_id: AccountId,
+        from_index: Option<u64>,
+        limit: Option<u64>,
+    ) -> Vec<HistoryReceiptOutput> {
+        self.account_history
+            .get(&account_id)
+            .unwrap_or_default()
+            .into_iter()
+            .rev()
+            .skip(from_index.unwrap_or(0) as usize)
+            .take(limit.unwrap_or(DEFAULT_PAGINATION_LIMIT) as usize)
+            .map(|receipt| receipt.into())
+            .collect()
+    }
+
+    pub fn get_history_count(&self, account_id: AccountId) -> u64 {
+        self.account_history
+            .get(&account_id)
+            .map(|history| history.len() as u64)
+            .unwrap_or(0)
+    }
+}
+
+impl SocialPremium {
+    #[allow(clippy::too_many_arguments)]
+    pub(crate) fn internal_add_history_receipt(
+        &mut self,
+        account_id: &AccountId,
+        kind: HistoryKind,
+        subscription_name: &SubscriptionName,
+        amount: Balance,
+        duration_ms: u128,
+        expires_at: u128,
+        referral_account_id: Option<ReferralAccountId>,
+        counterparty_id: Option<AccountId>,
+    ) {
+        let mut history = self.account_history.get(account_id).unwrap_or_default();
+
+        if history.len() >= MAX_HISTORY_SIZE {
+            history.remove(0);
+        }
+
+        history.push(HistoryReceipt {
+            operation_id: self.operations,
+            kind,
+            subscription_name: subscription_name.clone(),
+            amount,
+            duration_ms,
+            expires_at,
+            referral_account_id,
+            counterparty_id,
+            timestamp: env::block_timestamp_ms().into(),
+        });
+
+        self.account_history.insert(account_id, &history);
+    }
+}
@@ -0,0 +1,157 @@
+use crate::*;
+
+use near_sdk::serde_json::Value;
+
+pub const MAX_BACKFILL_BATCH_SIZE: usize = 50;
+
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct HolderOutput {
+    pub account_id: AccountId,
+    pub expires_at: U128,
+}
+
+#[near_bindgen]
+impl SocialPremium {
+    // from_index and limit select the range of the holders set, active_only filters it
+    pub fn get_holders(
+        &self,
+        name: SubscriptionName,
+        from_index: Option<u64>,
+        limit: Option<u64>,
+        active_only: Option<bool>,
+    ) -> Vec<HolderOutput> {
+        let holders = if let Some(holders) = self.subscription_holders.get(&name) {
+            holders
+        } else {
+            return vec![];
+        };
+
+        let now: u128 = env::block_timestamp_ms().into();
+        let active_only = active_only.unwrap_or(false);
+
+        holders
+            .iter()
+            .skip(from_index.unwrap_or(0) as usize)
+            .take(limit.unwrap_or(DEFAULT_PAGINATION_LIMIT) as usize)
+            .filter_map(|account_id| {
+                let expires_at = self
+                    .holder_expiries
+                    .get(&(name.clone(), account_id.clone()))
+                    .unwrap_or_default();
+
+                if active_only && expires_at <= now {
+                    None
+                } else {
+                    Some(HolderOutput {
+                        account_id,
+                        expires_at: U128::from(expires_at),
+                    })
+                }
+            })
+            .collect()
+    }
+
+    pub fn get_holders_count(&self, name: SubscriptionName) -> u64 {
+        self.subscription_holders
+            .get(&name)
+            .map(|holders| holders.len())
+            .unwrap_or(0)
+    }
+
+    // adds the badges written before the holders were tracked to the holders, expiry and
+    // status indexes, account ids are collected from the SocialDB off-chain
+    pub fn backfill_holders(
+        &mut self,
+        name: SubscriptionName,
+        account_ids: Vec<AccountId>,
+    ) -> Promise {
+        self.assert_owner();
+        self.assert_subscription(&name);
+        assert!(
+            !account_ids.is_empty() && account_ids.len() <= MAX_BACKFILL_BATCH_SIZE,
+            "ERR_INVALID_BATCH_SIZE"
+        );
+
+        let keys: Vec<String> = account_ids
+            .iter()
+            .map(|account_id| {
+                format!(
+                    "{}/badge/{}/accounts/{}",
+                    SOCIAL_PREMIUM_ACCOUNT_ID, name, account_id
+                )
+            })
+            .collect();
+
+        ext_social::ext(AccountId::new_unchecked(SOCIAL_DB_ACCOUNT_ID.to_string()))
+            .with_static_gas(GAS_FOR_SOCIAL_GET)
+            .get(keys, None)
+            .then(
+                ext_self::ext(env::current_account_id())
+                    .with_static_gas(GAS_FOR_AFTER_SOCIAL_GET_BATCH)
+                    .backfill_holders_after_social_get(name, account_ids),
+            )
+    }
+
+    // returns the number of indexed holders, accounts already known to the contract are skipped
+    #[private]
+    pub fn backfill_holders_after_social_get(
+        &mut self,
+        #[callback_result] value: Result<Value, PromiseError>,
+        subscription_name: SubscriptionName,
+        account_ids: Vec<AccountId>,
+    ) -> u64 {
+        let value = value.expect("ERR_SOCIAL_GET_FAILED");
+        let accounts = get_accounts_data(&value, &subscription_name);
+
+        let subscriptions: Vec<SubscriptionData> = account_ids
+            .into_iter()
+            .filter(|account_id| {
+                self.holder_expiries
+                    .get(&(subscription_name.clone(), account_id.clone()))
+                    .is_none()
+            })
+            .filter_map(|account_id| {
+                get_paid_until(accounts, &account_id).map(|timestamp| SubscriptionData {
+                    receiver_id: account_id,
+                    timestamp,
+                })
+            })
+            .collect();
+
+        self.internal_index_holders(&subscription_name, &subscriptions);
+
+        subscriptions.len() as u64
+    }
+}
+
+impl SocialPremium {
+    // accounts whose time was revoked or transferred away leave the holders set
+    pub(crate) fn internal_update_holders(
+        &mut self,
+        subscription_name: &SubscriptionName,
+        subscriptions: &[SubscriptionData],
+    ) {
+        let now: u128 = env::block_timestamp_ms().into();
+
+        let mut holders = self
+            .subscription_holders
+            .get(subscription_name)
+            .unwrap_or_else(|| {
+                UnorderedSet::new(StorageKey::SubscriptionHoldersPerSubscription {
+                    subscription_hash: env::sha256_array(subscription_name.as_bytes()),
+                })
+            });
+
+        for subscription in subscriptions {
+            if subscription.timestamp > now {
+                holders.insert(&subscription.receiver_id);
+            } else {
+                holders.remove(&subscription.receiver_id);
+            }
+        }
+
+        self.subscription_holders
+            .insert(subscription_name, &holders);
+    }
+}
@@ -0,0 +1,159 @@
+use crate::*;
+
+pub const LEADERBOARD_SIZE: usize = 50;
+
+type LeaderboardPeriod = u64;
+
+#[derive(BorshSerialize, BorshDeserialize, Default)]
+pub struct ReferralPeriodTotal {
+    pub volume: Balance,
+    pub rewards: Balance,
+}
+
+#[derive(BorshSerialize, BorshDeserialize)]
+pub struct LeaderboardEntry {
+    pub account_id: ReferralAccountId,
+    pub volume: Balance,
+    pub rewards: Balance,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct LeaderboardEntryOutput {
+    pub account_id: ReferralAccountId,
+    pub volume: U128,
+    pub rewards: U128,
+}
+
+impl From<LeaderboardEntry> for LeaderboardEntryOutput {
+    fn from(entry: LeaderboardEntry) -> Self {
+        LeaderboardEntryOutput {
+            account_id: entry.account_id,
+            volume: U128::from(entry.volume),
+            rewards: U128::from(entry.rewards),
+        }
+    }
+}
+
+#[near_bindgen]
+impl SocialPremium {
+    // periods are YEAR_IN_MS / 12 long buckets counted from the unix epoch, not calendar months
+    pub fn get_leaderboard_period(&self) -> LeaderboardPeriod {
+        get_current_leaderboard_period()
+    }
+
+    // the current period by default
+    pub fn get_referral_leaderboard(
+        &self,
+        period: Option<LeaderboardPeriod>,
+    ) -> Vec<LeaderboardEntryOutput> {
+        self.referral_leaderboards
+            .get(&period.unwrap_or_else(get_current_leaderboard_period))
+            .unwrap_or_default()
+            .into_iter()
+            .map(|entry| entry.into())
+            .collect()
+    }
+
+    pub fn get_referral_period_total(
+        &self,
+        referral_account_id: ReferralAccountId,
+        period: Option<LeaderboardPeriod>,
+    ) -> LeaderboardEntryOutput {
+        let total = self
+            .referral_period_totals
+            .get(&(
+                period.unwrap_or_else(get_current_leaderboard_period),
+                referral_account_id.clone(),
+            ))
+            .unwrap_or_default();
+
+        LeaderboardEntryOutput {
+            account_id: referral_account_id,
+            volume: U128::from(total.volume),
+            rewards: U128::from(total.rewards),
+        }
+    }
+}
+
+impl SocialPremium {
+    // adds the referred purchase to the totals of the current period and updates its top list
+    pub(crate) fn internal_update_leaderboard(
+        &mut self,
+        referral_account_id: &ReferralAccountId,
+        volume: Balance,
+        rewards: Balance,
+    ) {
+        let period = get_current_leaderboard_period();
+        let key = (period, referral_account_id.clone());
+
+        let mut total = self.referral_period_totals.get(&key).unwrap_or_default();
+        total.volume += volume;
+        total.rewards += rewards;
+        self.referral_period_totals.insert(&key, &total);
+
+        let mut leaderboard = self.referral_leaderboards.get(&period).unwrap_or_default();
+
+        if leaderboard.len() == LEADERBOARD_SIZE
+            && leaderboard
+                .last()
+                .map(|entry| (entry.volume, entry.rewards) >= (total.volume, total.rewards))
+                .unwrap_or(false)
+        {
+            return;
+        }
+
+        leaderboard.retain(|entry| &entry.account_id != referral_account_id);
+        leaderboard.push(LeaderboardEntry {
+            account_id: referral_account_id.clone(),
+            volume: total.volume,
+            rewards: total.rewards,
+        });
+        leaderboard.sort_by_key(|entry| std::cmp::Reverse((entry.volume, entry.rewards)));
+        leaderboard.truncate(LEADERBOARD_SIZE);
+
+        self.referral_leaderboards.insert(&period, &leaderboard);
+    }
+
+    // removes the refunded part of a referred purchase from the totals of its period,
+    // accounts outside of the top list are not promoted
+    pub(crate) fn internal_reduce_leaderboard(
+        &mut self,
+        referral_account_id: &ReferralAccountId,
+        purchased_at: u128,
+        volume: Balance,
+        rewards: Balance,
+    ) {
+        let period = get_leaderboard_period_at(purchased_at);
+        let key = (period, referral_account_id.clone());
+
+        let mut total = if let Some(total) = self.referral_period_totals.get(&key) {
+            total
+        } else {
+            return;
+        };
+        total.volume = total.volume.saturating_sub(volume);
+        total.rewards = total.rewards.saturating_sub(rewards);
+        self.referral_period_totals.insert(&key, &total);
+
+        let mut leaderboard = self.referral_leaderboards.get(&period).unwrap_or_default();
+        if let Some(entry) = leaderboard
+            .iter_mut()
+            .find(|entry| &entry.account_id == referral_account_id)
+        {
+            entry.volume = total.volume;
+            entry.rewards = total.rewards;
+            leaderboard.sort_by_key(|entry| std::cmp::Reverse((entry.volume, entry.rewards)));
+            self.referral_leaderboards.insert(&period, &leaderboard);
+        }
+    }
+}
+
+// average month long period, so the bounds drift from the calendar months
+fn get_current_leaderboard_period() -> LeaderboardPeriod {
+    get_leaderboard_period_at(env::block_timestamp_ms().into())
+}
+
+fn get_leaderboard_period_at(timestamp: u128) -> LeaderboardPeriod {
+    (timestamp / MONTH_IN_MS) as LeaderboardPeriod
+}
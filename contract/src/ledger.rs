@@ -0,0 +1,444 @@
+use crate::*;
+
+#[derive(Clone, Copy)]
+pub enum LedgerAccount {
+    // treasury funds the owner can withdraw
+    Available,
+    // funds kept for accrued and not yet claimed referral rewards
+    ReferralReserve,
+    // funds accrued to the revenue share beneficiaries and not yet distributed
+    RevenueShareReserve,
+    GrossRevenue,
+    ReferralPayouts,
+    StorageSpend,
+    Refunds,
+    Withdrawals,
+    RevenueDistributions,
+    KeeperFees,
+    // treasury balance carried over by the migration
+    OpeningBalance,
+}
+
+#[derive(BorshSerialize, BorshDeserialize, Default)]
+pub struct LedgerBalance {
+    pub debit: Balance,
+    pub credit: Balance,
+}
+
+#[derive(BorshSerialize, BorshDeserialize, Default)]
+pub struct Ledger {
+    pub available: LedgerBalance,
+    pub referral_reserve: LedgerBalance,
+    pub revenue_share_reserve: LedgerBalance,
+    pub gross_revenue: LedgerBalance,
+    pub referral_payouts: LedgerBalance,
+    pub storage_spend: LedgerBalance,
+    pub refunds: LedgerBalance,
+    pub withdrawals: LedgerBalance,
+    pub revenue_distributions: LedgerBalance,
+    pub keeper_fees: LedgerBalance,
+    pub opening_balance: LedgerBalance,
+    pub entries: u64,
+    // last seen SocialDB storage balance of the contract, unknown before the first SocialDB set
+    pub social_storage_total: Option<Balance>,
+    // storage balance growth not yet matched with the reserved SocialDB deposits
+    pub unmatched_social_storage: Balance,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct LedgerOutput {
+    pub available: U128,
+    // treasury spending not covered by the revenue yet
+    pub available_deficit: U128,
+    pub referral_reserve: U128,
+    pub revenue_share_reserve: U128,
+    pub gross_revenue: U128,
+    pub referral_payouts: U128,
+    pub storage_spend: U128,
+    pub refunds: U128,
+    pub withdrawals: U128,
+    pub revenue_distributions: U128,
+    pub keeper_fees: U128,
+    pub opening_balance: U128,
+    pub entries: u64,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct LedgerReconciliationOutput {
+    pub account_balance: U128,
+    pub storage_cost: U128,
+    // funds of the ledger asset accounts
+    pub ledger_balance: U128,
+    // contract balance not covered by the ledger, e.g. unused SocialDB deposits or trial deposits
+    pub surplus: U128,
+    // ledger funds missing from the contract balance
+    pub deficit: U128,
+    // treasury spending not covered by the revenue yet, e.g. SocialDB deposits paid
+    // before the first purchase
+    pub available_deficit: U128,
+}
+
+impl LedgerAccount {
+    fn is_credit_normal(&self) -> bool {
+        matches!(
+            self,
+            LedgerAccount::GrossRevenue | LedgerAccount::OpeningBalance
+        )
+    }
+
+    // the treasury pays the SocialDB deposits of badge updates which earn no revenue,
+    // e.g. trials or transfers, so it may go below zero
+    fn can_be_negative(&self) -> bool {
+        matches!(self, LedgerAccount::Available)
+    }
+}
+
+impl LedgerBalance {
+    // balance of debit-normal accounts
+    fn debit_balance(&self) -> Balance {
+        self.debit
+            .checked_sub(self.credit)
+            .expect("ERR_NEGATIVE_LEDGER_BALANCE")
+    }
+
+    // balance of credit-normal accounts
+    fn credit_balance(&self) -> Balance {
+        self.credit
+            .checked_sub(self.debit)
+            .expect("ERR_NEGATIVE_LEDGER_BALANCE")
+    }
+
+    fn assert_not_negative(&self, account: LedgerAccount) {
+        if account.is_credit_normal() {
+            self.credit_balance();
+        } else {
+            self.debit_balance();
+        }
+    }
+}
+
+impl Ledger {
+    fn get_balance_mut(&mut self, account: LedgerAccount) -> &mut LedgerBalance {
+        match account {
+            LedgerAccount::Available => &mut self.available,
+            LedgerAccount::ReferralReserve => &mut self.referral_reserve,
+            LedgerAccount::RevenueShareReserve => &mut self.revenue_share_reserve,
+            LedgerAccount::GrossRevenue => &mut self.gross_revenue,
+            LedgerAccount::ReferralPayouts => &mut self.referral_payouts,
+            LedgerAccount::StorageSpend => &mut self.storage_spend,
+            LedgerAccount::Refunds => &mut self.refunds,
+            LedgerAccount::Withdrawals => &mut self.withdrawals,
+            LedgerAccount::RevenueDistributions => &mut self.revenue_distributions,
+            LedgerAccount::KeeperFees => &mut self.keeper_fees,
+            LedgerAccount::OpeningBalance => &mut self.opening_balance,
+        }
+    }
+
+    pub fn get_available(&self) -> Balance {
+        self.available.debit.saturating_sub(self.available.credit)
+    }
+
+    pub fn get_available_deficit(&self) -> Balance {
+        self.available.credit.saturating_sub(self.available.debit)
+    }
+}
+
+#[near_bindgen]
+impl SocialPremium {
+    pub fn get_ledger(&self) -> LedgerOutput {
+        LedgerOutput {
+            available: U128::from(self.ledger.get_available()),
+            available_deficit: U128::from(self.ledger.get_available_deficit()),
+            referral_reserve: U128::from(self.ledger.referral_reserve.debit_balance()),
+            revenue_share_reserve: U128::from(self.ledger.revenue_share_reserve.debit_balance()),
+            gross_revenue: U128::from(self.ledger.gross_revenue.credit_balance()),
+            referral_payouts: U128::from(self.ledger.referral_payouts.debit_balance()),
+            storage_spend: U128::from(self.ledger.storage_spend.debit_balance()),
+            refunds: U128::from(self.ledger.refunds.debit_balance()),
+            withdrawals: U128::from(self.ledger.withdrawals.debit_balance()),
+            revenue_distributions: U128::from(self.ledger.revenue_distributions.debit_balance()),
+            keeper_fees: U128::from(self.ledger.keeper_fees.debit_balance()),
+            opening_balance: U128::from(self.ledger.opening_balance.credit_balance()),
+            entries: self.ledger.entries,
+        }
+    }
+
+    pub fn get_ledger_reconciliation(&self) -> LedgerReconciliationOutput {
+        let account_balance = env::account_balance();
+        let storage_cost = get_storage_cost();
+        let free_balance = account_balance.saturating_sub(storage_cost);
+
+        let available_deficit = self.ledger.get_available_deficit();
+        let ledger_balance = (self.ledger.get_available()
+            + self.ledger.referral_reserve.debit_balance()
+            + self.ledger.revenue_share_reserve.debit_balance())
+        .saturating_sub(available_deficit);
+
+        LedgerReconciliationOutput {
+            account_balance: U128::from(account_balance),
+            storage_cost: U128::from(storage_cost),
+            ledger_balance: U128::from(ledger_balance),
+            surplus: U128::from(free_balance.saturating_sub(ledger_balance)),
+            deficit: U128::from(ledger_balance.saturating_sub(free_balance)),
+            available_deficit: U128::from(available_deficit),
+        }
+    }
+
+    // returns the part of the reserved SocialDB deposit which was refunded to the treasury,
+    // growth of the SocialDB storage balance is the actual storage spend
+    #[private]
+    pub fn on_social_storage_balance(
+        &mut self,
+        #[callback_result] value: Result<Option<StorageBalance>, PromiseError>,
+        deposit: U128,
+    ) {
+        if let Ok(Some(storage_balance)) = value {
+            let total = storage_balance.total.0;
+
+            let refund = if let Some(prev_total) = self.ledger.social_storage_total {
+                self.ledger.unmatched_social_storage += total.saturating_sub(prev_total);
+                let refund = deposit
+                    .0
+                    .saturating_sub(self.ledger.unmatched_social_storage);
+                self.ledger.unmatched_social_storage = self
+                    .ledger
+                    .unmatched_social_storage
+                    .saturating_sub(deposit.0);
+                refund
+            } else {
+                // the first deposit is kept as spent, since the previous balance is unknown
+                0
+            };
+
+            self.ledger.social_storage_total = Some(std::cmp::max(
+                total,
+                self.ledger.social_storage_total.unwrap_or_default(),
+            ));
+
+            self.internal_post_ledger_entry(
+                LedgerAccount::Available,
+                LedgerAccount::StorageSpend,
+                refund,
+            );
+        }
+    }
+}
+
+impl SocialPremium {
+    // moves the amount from the credited account to the debited account
+    pub(crate) fn internal_post_ledger_entry(
+        &mut self,
+        debit: LedgerAccount,
+        credit: LedgerAccount,
+        amount: Balance,
+    ) {
+        if amount == 0 {
+            return;
+        }
+
+        self.ledger.get_balance_mut(debit).debit += amount;
+        self.ledger.get_balance_mut(credit).credit += amount;
+        self.ledger.entries += 1;
+
+        // an account can't spend more than its balance, e.g. the treasury can't pay
+        // the storage with the funds reserved for the referrals or revenue shares
+        for account in [debit, credit] {
+            if !account.can_be_negative() {
+                self.ledger
+                    .get_balance_mut(account)
+                    .assert_not_negative(account);
+            }
+        }
+    }
+
+    pub(crate) fn internal_withdraw_available(&mut self, amount: Balance) {
+        assert!(
+            self.ledger.get_available() >= amount,
+            "ERR_NOT_ENOUGH_DEPOSITS"
+        );
+
+        self.internal_post_ledger_entry(
+            LedgerAccount::Withdrawals,
+            LedgerAccount::Available,
+            amount,
+        );
+    }
+}
+
+pub(crate) fn get_storage_cost() -> Balance {
+    Balance::from(env::storage_usage()) * env::storage_byte_cost()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::*;
+    use near_sdk::PromiseResult;
+
+    #[test]
+    fn test_add_subscription_on_new_deployment() {
+        let contract = setup_contract(None);
+
+        let ledger = contract.get_ledger();
+        assert_eq!(ledger.available.0, 0);
+        assert_eq!(ledger.available_deficit.0, DEPOSIT_FOR_SOCIAL_SET);
+        assert_eq!(ledger.storage_spend.0, DEPOSIT_FOR_SOCIAL_SET);
+    }
+
+    #[test]
+    fn test_purchase_covers_deficit() {
+        let mut contract = setup_contract(None);
+
+        purchase(&mut contract, alice(), ONE_NEAR, None, NOW_MS);
+
+        let ledger = contract.get_ledger();
+        assert_eq!(ledger.gross_revenue.0, ONE_NEAR);
+        assert_eq!(ledger.storage_spend.0, 2 * DEPOSIT_FOR_SOCIAL_SET);
+        assert_eq!(ledger.available.0, ONE_NEAR - 2 * DEPOSIT_FOR_SOCIAL_SET);
+        assert_eq!(ledger.available_deficit.0, 0);
+    }
+
+    #[test]
+    fn test_refund() {
+        let mut contract = setup_contract(None);
+        contract.set_refund_policy(RefundPolicyInput {
+            window_ms: U128::from(DAY_IN_MS),
+            prorated: false,
+            fee: fee(0, 1),
+        });
+
+        purchase(&mut contract, bob(), 2 * ONE_NEAR, None, NOW_MS);
+        purchase(&mut contract, alice(), ONE_NEAR, None, NOW_MS);
+        let available = contract.get_ledger().available.0;
+        let operation_id = contract
+            .get_refundable_purchase(alice(), NAME.to_string())
+            .unwrap()
+            .operation_id;
+
+        set_callback_context(NOW_MS, PromiseResult::Successful(vec![]));
+        contract.refund_after_social_get(
+            Ok(get_badges(&contract, &[alice()])),
+            alice(),
+            NAME.to_string(),
+            None,
+        );
+        assert_eq!(contract.get_ledger().refunds.0, ONE_NEAR);
+
+        contract.on_refund_transferred(
+            alice(),
+            NAME.to_string(),
+            operation_id,
+            U128::from(ONE_NEAR),
+            U128::from(MONTH_IN_MS),
+            U128::from(NOW_MS),
+        );
+
+        let ledger = contract.get_ledger();
+        assert_eq!(ledger.refunds.0, ONE_NEAR);
+        assert_eq!(
+            ledger.available.0,
+            available - ONE_NEAR - DEPOSIT_FOR_SOCIAL_SET
+        );
+        assert!(contract
+            .get_refundable_purchase(alice(), NAME.to_string())
+            .is_none());
+    }
+
+    #[test]
+    fn test_failed_refund_transfer() {
+        let mut contract = setup_contract(None);
+        contract.set_refund_policy(RefundPolicyInput {
+            window_ms: U128::from(DAY_IN_MS),
+            prorated: false,
+            fee: fee(0, 1),
+        });
+
+        purchase(&mut contract, bob(), 2 * ONE_NEAR, None, NOW_MS);
+        purchase(&mut contract, alice(), ONE_NEAR, None, NOW_MS);
+        let available = contract.get_ledger().available.0;
+        let operation_id = contract
+            .get_refundable_purchase(alice(), NAME.to_string())
+            .unwrap()
+            .operation_id;
+
+        set_callback_context(NOW_MS, PromiseResult::Successful(vec![]));
+        contract.refund_after_social_get(
+            Ok(get_badges(&contract, &[alice()])),
+            alice(),
+            NAME.to_string(),
+            None,
+        );
+
+        set_callback_context(NOW_MS, PromiseResult::Failed);
+        contract.on_refund_transferred(
+            alice(),
+            NAME.to_string(),
+            operation_id,
+            U128::from(ONE_NEAR),
+            U128::from(MONTH_IN_MS),
+            U128::from(NOW_MS),
+        );
+
+        let ledger = contract.get_ledger();
+        assert_eq!(ledger.refunds.0, 0);
+        assert_eq!(ledger.available.0, available);
+        assert!(contract
+            .get_refundable_purchase(alice(), NAME.to_string())
+            .is_some());
+    }
+
+    #[test]
+    fn test_referral_payout() {
+        let mut contract = setup_contract(None);
+
+        purchase(&mut contract, alice(), ONE_NEAR, Some(bob()), NOW_MS);
+
+        let reward = ONE_NEAR / 10;
+        let ledger = contract.get_ledger();
+        assert_eq!(ledger.referral_reserve.0, reward);
+        assert_eq!(
+            ledger.available.0,
+            ONE_NEAR - reward - 2 * DEPOSIT_FOR_SOCIAL_SET
+        );
+
+        set_context(bob(), NOW_MS, 0);
+        contract.claim_referral_rewards();
+
+        let ledger = contract.get_ledger();
+        assert_eq!(ledger.referral_reserve.0, 0);
+        assert_eq!(ledger.referral_payouts.0, reward);
+
+        // a failed transfer restores the reserved reward
+        set_callback_context(NOW_MS, PromiseResult::Failed);
+        contract.on_referral_rewards_claimed(bob(), U128::from(reward));
+
+        let ledger = contract.get_ledger();
+        assert_eq!(ledger.referral_reserve.0, reward);
+        assert_eq!(ledger.referral_payouts.0, 0);
+        assert_eq!(contract.get_referral_balance(bob()).0, reward);
+    }
+
+    #[test]
+    fn test_ledger_reconciliation() {
+        let mut contract = setup_contract(None);
+
+        let reconciliation = contract.get_ledger_reconciliation();
+        assert_eq!(reconciliation.ledger_balance.0, 0);
+        assert_eq!(reconciliation.available_deficit.0, DEPOSIT_FOR_SOCIAL_SET);
+
+        purchase(&mut contract, alice(), ONE_NEAR, Some(bob()), NOW_MS);
+
+        let reconciliation = contract.get_ledger_reconciliation();
+        assert_eq!(reconciliation.available_deficit.0, 0);
+        assert_eq!(
+            reconciliation.ledger_balance.0,
+            ONE_NEAR - 2 * DEPOSIT_FOR_SOCIAL_SET
+        );
+        assert_eq!(
+            reconciliation.surplus.0 + reconciliation.ledger_balance.0 - reconciliation.deficit.0,
+            reconciliation.account_balance.0 - reconciliation.storage_cost.0
+        );
+    }
+}
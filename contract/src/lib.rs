@@ -2,12 +2,16 @@ use near_sdk::json_types::U128;
 use near_sdk::{
     borsh::{self, BorshDeserialize, BorshSerialize},
     collections::LookupMap,
+    collections::LookupSet,
+    collections::TreeMap,
     collections::UnorderedMap,
+    collections::UnorderedSet,
     env, ext_contract, log, near_bindgen,
     serde::{Deserialize, Serialize},
-    AccountId, Balance, BlockHeight, BorshStorageKey, Gas, PanicOnDefault, Promise, PromiseError,
-    ONE_YOCTO,
+    AccountId, Balance, BlockHeight, BorshStorageKey, CryptoHash, Gas, PanicOnDefault, Promise,
+    PromiseError, StorageUsage, ONE_YOCTO,
 };
+use std::collections::HashMap;
 
 const SOCIAL_DB_ACCOUNT_ID: &str = "social.near";
 const SOCIAL_PREMIUM_ACCOUNT_ID: &str = "premium.social.near";
@@ -16,20 +20,61 @@ const SOCIAL_PREMIUM_ACCOUNT_ID: &str = "premium.social.near";
 
 const BLOCKS_NUM_TO_LOCK_ACCOUNT: BlockHeight = 60;
 
+mod approval;
+mod cleanup;
+mod commission;
+mod expiry_index;
+mod history;
+mod holders;
+mod leaderboard;
+mod ledger;
 mod migration;
+mod partner;
+mod referral;
+mod referral_code;
+mod referral_rules;
+mod refund;
+mod revenue_share;
+mod seats;
+mod shared_plan;
 mod social;
+mod stats;
+mod status;
 mod subscription;
+#[cfg(test)]
+mod test_utils;
+mod trial;
 mod utils;
 
+use crate::approval::*;
+use crate::cleanup::*;
+use crate::commission::*;
+use crate::history::*;
+use crate::holders::*;
+use crate::leaderboard::*;
+use crate::ledger::*;
+use crate::partner::*;
+use crate::referral::*;
+use crate::referral_rules::*;
+use crate::refund::*;
+use crate::revenue_share::*;
+use crate::seats::*;
+use crate::shared_plan::*;
 use crate::social::*;
+use crate::stats::*;
 use crate::subscription::*;
-use crate::utils::FeeFraction;
+use crate::trial::*;
+use crate::utils::*;
 
 type SubscriptionName = String;
 type ReferralAccountId = AccountId;
+type ReferralCode = String;
 
 const SOCIAL_PREMIUM_TREASURY_ACCOUNT_ID: &str = "treasury.premium.social.near";
 const YEAR_IN_MS: u128 = 31556926000;
+const MONTH_IN_MS: u128 = YEAR_IN_MS / 12;
+const DAY_IN_MS: u128 = 86400000;
+const DEFAULT_PAGINATION_LIMIT: u64 = 100;
 
 #[derive(BorshSerialize, BorshStorageKey)]
 enum StorageKey {
@@ -37,6 +82,44 @@ enum StorageKey {
     AccountLocks,
     Referrals,
     ReferralRewards,
+    Approvals,
+    SeatPools,
+    SeatMembers,
+    SharedPlans,
+    SharedPlanMembers,
+    TrialClaims,
+    ReferralBalances,
+    PendingReferralRewards,
+    Affiliates,
+    AffiliatesPerReferral { account_hash: CryptoHash },
+    ReferralBindings,
+    SecondLevelReferralRewards,
+    Partners,
+    ReferralCodes,
+    ReferralPeriodRewards,
+    ReferralPairRewardedAt,
+    ReferralPeriodTotals,
+    ReferralLeaderboards,
+    FirstReferredPurchases,
+    ReferralCommissions,
+    RevenueShareBalances,
+    RefundablePurchases,
+    AccountHistory,
+    SubscriptionStats,
+    SubscriptionBuyers,
+    HolderExpiries,
+    HolderExpiryCounts,
+    HolderExpiryCountsPerSubscription { subscription_hash: CryptoHash },
+    DailyRevenue,
+    MonthlyRevenue,
+    SubscriptionHolders,
+    SubscriptionHoldersPerSubscription { subscription_hash: CryptoHash },
+    ExpiryIndex,
+    ExpiryIndexPerSubscription { subscription_hash: CryptoHash },
+    PendingReferralRewardsPerAccount { account_hash: CryptoHash },
+    FormerHolders,
+    PaidHolders,
+    RevokedHolders,
 }
 
 #[near_bindgen]
@@ -61,6 +144,94 @@ pub struct SocialPremium {
     referral_rewards: LookupMap<ReferralAccountId, Balance>,
     // total historical rewards
     total_referral_rewards: Balance,
+    // transfer approvals given by holders to spenders
+    approvals: LookupMap<(AccountId, SubscriptionName), HashMap<AccountId, Approval>>,
+    // seat pools purchased by organizations
+    seat_pools: LookupMap<(AccountId, SubscriptionName), SeatPool>,
+    // organizations of the accounts with an assigned seat
+    seat_members: LookupMap<(AccountId, SubscriptionName), AccountId>,
+    // shared plans by payer
+    shared_plans: LookupMap<(AccountId, SubscriptionName), SharedPlan>,
+    // shared plan of every payer and member
+    shared_plan_members: LookupMap<AccountId, (AccountId, SubscriptionName)>,
+    // trials claimed by accounts
+    trial_claims: LookupMap<(AccountId, SubscriptionName), TrialClaim>,
+    // unclaimed rewards for each referral
+    referral_balances: LookupMap<ReferralAccountId, Balance>,
+    // period before a referral reward can be claimed
+    referral_holdback_ms: u128,
+    // rewards of each referral in the holdback period
+    pending_referral_rewards: LookupMap<ReferralAccountId, PendingReferralRewards>,
+    // list of accounts referred by each referral
+    affiliates: LookupMap<ReferralAccountId, UnorderedSet<AccountId>>,
+    // policy to choose between stored and provided referrals
+    referral_attribution: ReferralAttribution,
+    // period after which a referral binding expires
+    referral_window_ms: Option<u128>,
+    // timestamps of the referral bindings
+    referral_bindings: LookupMap<AccountId, u128>,
+    // bind timestamp of the referrals stored before bindings were tracked
+    legacy_referrals_bound_at: u128,
+    // referral fee for the referral of the referral
+    second_level_referral_fee: FeeFraction,
+    // historical second level rewards for each referral
+    second_level_referral_rewards: LookupMap<ReferralAccountId, Balance>,
+    // referrals with custom fees
+    partners: UnorderedMap<ReferralAccountId, Partner>,
+    // short codes mapped to referral accounts
+    referral_codes: LookupMap<ReferralCode, ReferralAccountId>,
+    // anti-sybil rules for referral rewards
+    referral_rules: ReferralRules,
+    // rewards of each referral in the current cap period
+    referral_period_rewards: LookupMap<ReferralAccountId, (u64, Balance)>,
+    // last rewarded purchase of each referral and receiver pair
+    referral_pair_rewarded_at: LookupMap<(ReferralAccountId, AccountId), u128>,
+    // referred volume and rewards of each referral per leaderboard period
+    referral_period_totals: LookupMap<(u64, ReferralAccountId), ReferralPeriodTotal>,
+    // top referrals of each leaderboard period
+    referral_leaderboards: LookupMap<u64, Vec<LeaderboardEntry>>,
+    // referral fees for purchases made some time after the first referred purchase
+    renewal_commissions: Vec<RenewalCommission>,
+    // timestamps of the first referred purchase of each account
+    first_referred_purchases: LookupMap<AccountId, u128>,
+    // referral commissions applied to each purchase operation
+    referral_commissions: LookupMap<u64, ReferralCommission>,
+    // double-entry accounting of the treasury
+    ledger: Ledger,
+    // beneficiaries of the net revenue
+    revenue_shares: Vec<RevenueShare>,
+    // accrued and not yet distributed revenue shares
+    revenue_share_balances: UnorderedMap<AccountId, Balance>,
+    // refund window, proration and fee
+    refund_policy: RefundPolicy,
+    // last purchase of each holder which can be refunded
+    refundable_purchases: LookupMap<(AccountId, SubscriptionName), RefundablePurchase>,
+    // latest purchases, transfers and refunds of each account
+    account_history: LookupMap<AccountId, Vec<HistoryReceipt>>,
+    // revenue and holder counters of each subscription
+    subscription_stats: LookupMap<SubscriptionName, SubscriptionStats>,
+    // accounts which ever paid for each subscription
+    subscription_buyers: LookupSet<(SubscriptionName, AccountId)>,
+    // timestamps written to the badges of the holders
+    holder_expiries: LookupMap<(SubscriptionName, AccountId), u128>,
+    // number of active holders expiring at each timestamp
+    holder_expiry_counts: LookupMap<SubscriptionName, TreeMap<u128, u64>>,
+    // revenue of each subscription per day
+    daily_revenue: LookupMap<(SubscriptionName, u64), Balance>,
+    // revenue of each subscription per YEAR_IN_MS / 12 long period
+    monthly_revenue: LookupMap<(SubscriptionName, u64), Balance>,
+    // holders with a not expired badge of each subscription
+    subscription_holders: LookupMap<SubscriptionName, UnorderedSet<AccountId>>,
+    // holders of each subscription ordered by expiry
+    expiry_index: LookupMap<SubscriptionName, TreeMap<(u128, AccountId), ()>>,
+    // grace period and keeper fee of the expired badges cleanup
+    cleanup_config: CleanupConfig,
+    // holders whose expired badges were removed by the cleanup
+    former_holders: LookupSet<(SubscriptionName, AccountId)>,
+    // holders whose badge was extended by a purchase for them
+    paid_holders: LookupSet<(SubscriptionName, AccountId)>,
+    // holders whose time was transferred away, refunded or unassigned, they get no grace period
+    revoked_holders: LookupSet<(SubscriptionName, AccountId)>,
 }
 
 #[near_bindgen]
@@ -85,6 +256,53 @@ impl SocialPremium {
             premium_referral_fee,
             referral_rewards: LookupMap::new(StorageKey::ReferralRewards),
             total_referral_rewards: 0,
+            approvals: LookupMap::new(StorageKey::Approvals),
+            seat_pools: LookupMap::new(StorageKey::SeatPools),
+            seat_members: LookupMap::new(StorageKey::SeatMembers),
+            shared_plans: LookupMap::new(StorageKey::SharedPlans),
+            shared_plan_members: LookupMap::new(StorageKey::SharedPlanMembers),
+            trial_claims: LookupMap::new(StorageKey::TrialClaims),
+            referral_balances: LookupMap::new(StorageKey::ReferralBalances),
+            referral_holdback_ms: 0,
+            pending_referral_rewards: LookupMap::new(StorageKey::PendingReferralRewards),
+            affiliates: LookupMap::new(StorageKey::Affiliates),
+            referral_attribution: ReferralAttribution::LastTouch,
+            referral_window_ms: None,
+            referral_bindings: LookupMap::new(StorageKey::ReferralBindings),
+            legacy_referrals_bound_at: 0,
+            second_level_referral_fee: FeeFraction {
+                numerator: 0,
+                denominator: 1,
+            },
+            second_level_referral_rewards: LookupMap::new(StorageKey::SecondLevelReferralRewards),
+            partners: UnorderedMap::new(StorageKey::Partners),
+            referral_codes: LookupMap::new(StorageKey::ReferralCodes),
+            referral_rules: ReferralRules::default(),
+            referral_period_rewards: LookupMap::new(StorageKey::ReferralPeriodRewards),
+            referral_pair_rewarded_at: LookupMap::new(StorageKey::ReferralPairRewardedAt),
+            referral_period_totals: LookupMap::new(StorageKey::ReferralPeriodTotals),
+            referral_leaderboards: LookupMap::new(StorageKey::ReferralLeaderboards),
+            renewal_commissions: Vec::new(),
+            first_referred_purchases: LookupMap::new(StorageKey::FirstReferredPurchases),
+            referral_commissions: LookupMap::new(StorageKey::ReferralCommissions),
+            ledger: Ledger::default(),
+            revenue_shares: Vec::new(),
+            revenue_share_balances: UnorderedMap::new(StorageKey::RevenueShareBalances),
+            refund_policy: RefundPolicy::default(),
+            refundable_purchases: LookupMap::new(StorageKey::RefundablePurchases),
+            account_history: LookupMap::new(StorageKey::AccountHistory),
+            subscription_stats: LookupMap::new(StorageKey::SubscriptionStats),
+            subscription_buyers: LookupSet::new(StorageKey::SubscriptionBuyers),
+            holder_expiries: LookupMap::new(StorageKey::HolderExpiries),
+            holder_expiry_counts: LookupMap::new(StorageKey::HolderExpiryCounts),
+            daily_revenue: LookupMap::new(StorageKey::DailyRevenue),
+            monthly_revenue: LookupMap::new(StorageKey::MonthlyRevenue),
+            subscription_holders: LookupMap::new(StorageKey::SubscriptionHolders),
+            expiry_index: LookupMap::new(StorageKey::ExpiryIndex),
+            cleanup_config: CleanupConfig::default(),
+            former_holders: LookupSet::new(StorageKey::FormerHolders),
+            paid_holders: LookupSet::new(StorageKey::PaidHolders),
+            revoked_holders: LookupSet::new(StorageKey::RevokedHolders),
         }
     }
 
@@ -94,23 +312,19 @@ impl SocialPremium {
         name: SubscriptionName,
         receiver_id: Option<AccountId>,
         referral_id: Option<ReferralAccountId>,
+        referral_code: Option<ReferralCode>,
     ) -> Promise {
         let receiver_id = receiver_id.unwrap_or(env::predecessor_account_id());
 
-        let referral_account_id = if let Some(referral_id) = referral_id {
-            // referral id was provided in the request
-            assert!(referral_id != receiver_id, "ERR_SELF_REFERRAL_NOT_ALLOWED");
-            Some(referral_id)
-        } else {
-            // previously stored referral id
-            self.referrals.get(&receiver_id)
-        };
+        let referral_id = self.internal_resolve_referral(referral_id, referral_code);
+        let referral_account_id = self.internal_get_referral(&receiver_id, referral_id);
 
         let deposit = env::attached_deposit();
         assert!(deposit >= MIN_DEPOSIT, "Deposit {} required", MIN_DEPOSIT);
 
         self.lock_account(&receiver_id);
         self.assert_subscription(&name);
+        self.assert_can_purchase(&name, &receiver_id);
 
         let mut keys: Vec<String> = vec![format!(
             "{}/badge/{}/accounts/{}",
@@ -136,6 +350,7 @@ impl SocialPremium {
                         name,
                         U128::from(deposit),
                         referral_account_id,
+                        env::predecessor_account_id(),
                     ),
             )
             .then(
@@ -146,55 +361,118 @@ impl SocialPremium {
     }
 
     #[payable]
-    pub fn transfer(&mut self, name: SubscriptionName, receiver_id: AccountId) -> Promise {
-        assert_eq!(env::attached_deposit(), ONE_YOCTO, "ERR_ONE_YOCTO_REQUIRED");
+    pub fn purchase_batch(
+        &mut self,
+        name: SubscriptionName,
+        receivers: Vec<(AccountId, U128)>,
+        referral_id: Option<ReferralAccountId>,
+        referral_code: Option<ReferralCode>,
+    ) -> Promise {
+        assert!(!receivers.is_empty(), "ERR_NO_RECEIVERS");
+        assert!(
+            receivers.len() <= MAX_PURCHASE_BATCH_SIZE,
+            "ERR_TOO_MANY_RECEIVERS"
+        );
+
+        let total_amount: Balance = receivers.iter().map(|(_, amount)| amount.0).sum();
+        assert!(
+            total_amount >= MIN_DEPOSIT,
+            "Deposit {} required",
+            MIN_DEPOSIT
+        );
 
-        let sender_id = env::predecessor_account_id();
+        let deposit = env::attached_deposit();
+        assert!(deposit >= total_amount, "Deposit {} required", total_amount);
 
         self.assert_subscription(&name);
 
-        assert_ne!(receiver_id, sender_id, "ERR_SENDER_IS_RECEIVER");
-        self.lock_account(&receiver_id);
-        self.lock_account(&sender_id);
+        let referral_id = self.internal_resolve_referral(referral_id, referral_code);
 
-        let keys: Vec<String> = vec![
-            format!(
-                "{}/badge/{}/accounts/{}",
-                SOCIAL_PREMIUM_ACCOUNT_ID, name, sender_id
-            ),
-            format!(
+        let mut keys: Vec<String> = Vec::new();
+        let mut entries: Vec<PurchaseEntry> = Vec::new();
+        let mut receiver_ids: Vec<AccountId> = Vec::new();
+
+        for (receiver_id, amount) in receivers {
+            assert!(amount.0 > 0, "ERR_ZERO_AMOUNT");
+            assert!(
+                !receiver_ids.contains(&receiver_id),
+                "ERR_DUPLICATE_RECEIVER"
+            );
+
+            let referral_account_id = self.internal_get_referral(&receiver_id, referral_id.clone());
+
+            self.assert_can_purchase(&name, &receiver_id);
+            self.lock_account(&receiver_id);
+
+            keys.push(format!(
                 "{}/badge/{}/accounts/{}",
                 SOCIAL_PREMIUM_ACCOUNT_ID, name, receiver_id
-            ),
-        ];
+            ));
+
+            if let Some(referral_id) = referral_account_id.clone() {
+                // check if referral_id is premium
+                let referral_key = format!(
+                    "{}/badge/{}/accounts/{}",
+                    SOCIAL_PREMIUM_ACCOUNT_ID, name, referral_id
+                );
+                if !keys.contains(&referral_key) {
+                    keys.push(referral_key);
+                }
+            }
+
+            receiver_ids.push(receiver_id.clone());
+            entries.push(PurchaseEntry {
+                receiver_id,
+                amount,
+                referral_account_id,
+            });
+        }
+
+        // return the deposit not covered by the entries
+        let refund = deposit - total_amount;
+        if refund > 0 {
+            Promise::new(env::predecessor_account_id()).transfer(refund);
+        }
 
         ext_social::ext(AccountId::new_unchecked(SOCIAL_DB_ACCOUNT_ID.to_string()))
             .with_static_gas(GAS_FOR_SOCIAL_GET)
             .get(keys, None)
             .then(
                 ext_self::ext(env::current_account_id())
-                    .with_static_gas(GAS_FOR_AFTER_SOCIAL_GET)
-                    .transfer_after_social_get(sender_id.clone(), receiver_id.clone(), name),
+                    .with_static_gas(GAS_FOR_AFTER_SOCIAL_GET_BATCH)
+                    .purchase_batch_after_social_get(name, entries, env::predecessor_account_id()),
             )
             .then(
                 ext_self::ext(env::current_account_id())
                     .with_static_gas(GAS_FOR_UNLOCK)
-                    .unlock_accounts(vec![sender_id, receiver_id]),
+                    .unlock_accounts(receiver_ids),
             )
     }
 
+    #[payable]
+    pub fn transfer(&mut self, name: SubscriptionName, receiver_id: AccountId) -> Promise {
+        assert_eq!(env::attached_deposit(), ONE_YOCTO, "ERR_ONE_YOCTO_REQUIRED");
+
+        let sender_id = env::predecessor_account_id();
+
+        self.internal_transfer(name, sender_id, receiver_id, None, None)
+    }
+
     pub fn set_referral_fee(&mut self, referral_fee: FeeFraction) {
         self.assert_owner();
         referral_fee.assert_valid();
         self.referral_fee = referral_fee;
+        self.assert_referral_fees_valid();
     }
 
     pub fn set_premium_referral_fee(&mut self, premium_referral_fee: FeeFraction) {
         self.assert_owner();
         premium_referral_fee.assert_valid();
         self.premium_referral_fee = premium_referral_fee;
+        self.assert_referral_fees_valid();
     }
 
+    #[allow(clippy::too_many_arguments)]
     pub fn add_subscription(
         &mut self,
         name: SubscriptionName,
@@ -203,15 +481,41 @@ impl SocialPremium {
         image_url: String,
         price: U128,
         price_wholesale: U128,
+        max_members: Option<u32>,
     ) {
         self.assert_owner();
 
+        let kind = if let Some(max_members) = max_members {
+            assert!(max_members > 0, "ERR_ZERO_MAX_MEMBERS");
+            SubscriptionKind::Shared { max_members }
+        } else {
+            SubscriptionKind::Individual
+        };
+
+        // trial and grace settings of the updated subscription are kept
+        let (trial_duration_ms, trial_deposit, grace_period_ms) = self
+            .subscriptions
+            .get(&name)
+            .map(|subscription| {
+                let subscription = Subscription::from(subscription);
+                (
+                    subscription.trial_duration_ms,
+                    subscription.trial_deposit,
+                    subscription.grace_period_ms,
+                )
+            })
+            .unwrap_or((None, 0, 0));
+
         let subscription = Subscription {
             title,
             description,
             image_url,
             price: price.0,
             price_wholesale: price_wholesale.0,
+            kind,
+            trial_duration_ms,
+            trial_deposit,
+            grace_period_ms,
         };
 
         self.subscriptions
@@ -240,15 +544,31 @@ impl SocialPremium {
     }
 
     pub fn get_deposits_without_referral_fees(&self) -> U128 {
-        U128::from(self.deposits - self.total_referral_rewards)
+        U128::from(self.ledger.get_available())
     }
 
-    pub fn get_affiliates(&self, referral_account_id: ReferralAccountId) -> Vec<AccountId> {
-        self.referrals
-            .into_iter()
-            .filter(|(_, _referral_account_id)| (referral_account_id == *_referral_account_id))
-            .map(|(user_account_id, _)| user_account_id)
-            .collect()
+    pub fn get_affiliates(
+        &self,
+        referral_account_id: ReferralAccountId,
+        from_index: Option<u64>,
+        limit: Option<u64>,
+    ) -> Vec<AccountId> {
+        if let Some(affiliates) = self.affiliates.get(&referral_account_id) {
+            affiliates
+                .iter()
+                .skip(from_index.unwrap_or(0) as usize)
+                .take(limit.unwrap_or(DEFAULT_PAGINATION_LIMIT) as usize)
+                .collect()
+        } else {
+            vec![]
+        }
+    }
+
+    pub fn get_affiliates_count(&self, referral_account_id: ReferralAccountId) -> u64 {
+        self.affiliates
+            .get(&referral_account_id)
+            .map(|affiliates| affiliates.len())
+            .unwrap_or(0)
     }
 
     pub fn get_deposits(&self) -> U128 {
@@ -282,9 +602,10 @@ impl SocialPremium {
     ) -> Promise {
         self.assert_owner();
 
-        assert!(self.deposits >= amount.0, "ERR_NOT_ENOUGH_DEPOSITS");
+        // only funds not reserved for referral rewards or spent on storage can be withdrawn
+        self.internal_withdraw_available(amount.0);
 
-        self.deposits -= amount.0;
+        self.deposits = self.deposits.saturating_sub(amount.0);
 
         let destination_account_id = destination_account_id.unwrap_or(AccountId::new_unchecked(
             SOCIAL_PREMIUM_TREASURY_ACCOUNT_ID.to_string(),
@@ -300,6 +621,58 @@ impl SocialPremium {
 }
 
 impl SocialPremium {
+    pub(crate) fn internal_transfer(
+        &mut self,
+        name: SubscriptionName,
+        sender_id: AccountId,
+        receiver_id: AccountId,
+        duration_ms: Option<U128>,
+        spender_id: Option<AccountId>,
+    ) -> Promise {
+        self.assert_subscription(&name);
+        self.assert_not_shared(&name);
+
+        assert_ne!(receiver_id, sender_id, "ERR_SENDER_IS_RECEIVER");
+        self.assert_not_seated(&name, &sender_id);
+        self.assert_not_seated(&name, &receiver_id);
+        self.assert_no_active_trial(&name, &sender_id);
+        self.internal_vacate_expired_seat(&name, &sender_id);
+        self.internal_vacate_expired_seat(&name, &receiver_id);
+        self.lock_account(&receiver_id);
+        self.lock_account(&sender_id);
+
+        let keys: Vec<String> = vec![
+            format!(
+                "{}/badge/{}/accounts/{}",
+                SOCIAL_PREMIUM_ACCOUNT_ID, name, sender_id
+            ),
+            format!(
+                "{}/badge/{}/accounts/{}",
+                SOCIAL_PREMIUM_ACCOUNT_ID, name, receiver_id
+            ),
+        ];
+
+        ext_social::ext(AccountId::new_unchecked(SOCIAL_DB_ACCOUNT_ID.to_string()))
+            .with_static_gas(GAS_FOR_SOCIAL_GET)
+            .get(keys, None)
+            .then(
+                ext_self::ext(env::current_account_id())
+                    .with_static_gas(GAS_FOR_AFTER_SOCIAL_GET)
+                    .transfer_after_social_get(
+                        sender_id.clone(),
+                        receiver_id.clone(),
+                        name,
+                        duration_ms,
+                        spender_id,
+                    ),
+            )
+            .then(
+                ext_self::ext(env::current_account_id())
+                    .with_static_gas(GAS_FOR_UNLOCK)
+                    .unlock_accounts(vec![sender_id, receiver_id]),
+            )
+    }
+
     pub(crate) fn internal_get_subscription(
         &self,
         subscription_name: &SubscriptionName,
@@ -311,6 +684,13 @@ impl SocialPremium {
         )
     }
 
+    pub(crate) fn is_account_locked(&self, account_id: &AccountId) -> bool {
+        self.account_locks
+            .get(account_id)
+            .map(|account_lock| account_lock + BLOCKS_NUM_TO_LOCK_ACCOUNT > env::block_height())
+            .unwrap_or(false)
+    }
+
     pub(crate) fn internal_unlock_account(&mut self, account_id: &AccountId) {
         self.account_locks.remove(account_id);
     }
@@ -5,10 +5,7 @@ impl SocialPremium {
     #[private]
     #[init(ignore_state)]
     #[allow(dead_code)]
-    pub fn migrate(referral_fee: FeeFraction, premium_referral_fee: FeeFraction) -> Self {
-        referral_fee.assert_valid();
-        premium_referral_fee.assert_valid();
-
+    pub fn migrate() -> Self {
         #[derive(BorshDeserialize)]
         struct OldContract {
             owner_id: AccountId,
@@ -16,21 +13,89 @@ impl SocialPremium {
             account_locks: LookupMap<AccountId, BlockHeight>,
             deposits: Balance,
             operations: u64,
+            referrals: UnorderedMap<AccountId, ReferralAccountId>,
+            referral_fee: FeeFraction,
+            premium_referral_fee: FeeFraction,
+            referral_rewards: LookupMap<ReferralAccountId, Balance>,
+            total_referral_rewards: Balance,
         }
 
         let old_contract: OldContract = env::state_read().expect("Old state doesn't exist");
 
-        Self {
+        // the treasury left after withdrawals and paid out referral rewards, capped by the free balance
+        let opening_balance = std::cmp::min(
+            old_contract
+                .deposits
+                .saturating_sub(old_contract.total_referral_rewards),
+            env::account_balance().saturating_sub(get_storage_cost()),
+        );
+
+        let mut contract = Self {
             owner_id: old_contract.owner_id,
             subscriptions: old_contract.subscriptions,
             account_locks: old_contract.account_locks,
             deposits: old_contract.deposits,
             operations: old_contract.operations,
-            referrals: UnorderedMap::new(StorageKey::Referrals),
-            referral_fee,
-            premium_referral_fee,
-            referral_rewards: LookupMap::new(StorageKey::ReferralRewards),
-            total_referral_rewards: 0,
-        }
+            referrals: old_contract.referrals,
+            referral_fee: old_contract.referral_fee,
+            premium_referral_fee: old_contract.premium_referral_fee,
+            referral_rewards: old_contract.referral_rewards,
+            total_referral_rewards: old_contract.total_referral_rewards,
+            approvals: LookupMap::new(StorageKey::Approvals),
+            seat_pools: LookupMap::new(StorageKey::SeatPools),
+            seat_members: LookupMap::new(StorageKey::SeatMembers),
+            shared_plans: LookupMap::new(StorageKey::SharedPlans),
+            shared_plan_members: LookupMap::new(StorageKey::SharedPlanMembers),
+            trial_claims: LookupMap::new(StorageKey::TrialClaims),
+            referral_balances: LookupMap::new(StorageKey::ReferralBalances),
+            referral_holdback_ms: 0,
+            pending_referral_rewards: LookupMap::new(StorageKey::PendingReferralRewards),
+            affiliates: LookupMap::new(StorageKey::Affiliates),
+            referral_attribution: ReferralAttribution::LastTouch,
+            referral_window_ms: None,
+            referral_bindings: LookupMap::new(StorageKey::ReferralBindings),
+            legacy_referrals_bound_at: env::block_timestamp_ms().into(),
+            second_level_referral_fee: FeeFraction {
+                numerator: 0,
+                denominator: 1,
+            },
+            second_level_referral_rewards: LookupMap::new(StorageKey::SecondLevelReferralRewards),
+            partners: UnorderedMap::new(StorageKey::Partners),
+            referral_codes: LookupMap::new(StorageKey::ReferralCodes),
+            referral_rules: ReferralRules::default(),
+            referral_period_rewards: LookupMap::new(StorageKey::ReferralPeriodRewards),
+            referral_pair_rewarded_at: LookupMap::new(StorageKey::ReferralPairRewardedAt),
+            referral_period_totals: LookupMap::new(StorageKey::ReferralPeriodTotals),
+            referral_leaderboards: LookupMap::new(StorageKey::ReferralLeaderboards),
+            renewal_commissions: Vec::new(),
+            first_referred_purchases: LookupMap::new(StorageKey::FirstReferredPurchases),
+            referral_commissions: LookupMap::new(StorageKey::ReferralCommissions),
+            ledger: Ledger::default(),
+            revenue_shares: Vec::new(),
+            revenue_share_balances: UnorderedMap::new(StorageKey::RevenueShareBalances),
+            refund_policy: RefundPolicy::default(),
+            refundable_purchases: LookupMap::new(StorageKey::RefundablePurchases),
+            account_history: LookupMap::new(StorageKey::AccountHistory),
+            subscription_stats: LookupMap::new(StorageKey::SubscriptionStats),
+            subscription_buyers: LookupSet::new(StorageKey::SubscriptionBuyers),
+            holder_expiries: LookupMap::new(StorageKey::HolderExpiries),
+            holder_expiry_counts: LookupMap::new(StorageKey::HolderExpiryCounts),
+            daily_revenue: LookupMap::new(StorageKey::DailyRevenue),
+            monthly_revenue: LookupMap::new(StorageKey::MonthlyRevenue),
+            subscription_holders: LookupMap::new(StorageKey::SubscriptionHolders),
+            expiry_index: LookupMap::new(StorageKey::ExpiryIndex),
+            cleanup_config: CleanupConfig::default(),
+            former_holders: LookupSet::new(StorageKey::FormerHolders),
+            paid_holders: LookupSet::new(StorageKey::PaidHolders),
+            revoked_holders: LookupSet::new(StorageKey::RevokedHolders),
+        };
+
+        contract.internal_post_ledger_entry(
+            LedgerAccount::Available,
+            LedgerAccount::OpeningBalance,
+            opening_balance,
+        );
+
+        contract
     }
 }
@@ -0,0 +1,141 @@
+use crate::*;
+
+#[derive(BorshSerialize, BorshDeserialize)]
+pub struct Partner {
+    // custom fee replacing the regular and premium referral fees
+    pub fee: FeeFraction,
+    // fees applied once the referred volume reaches min_volume
+    pub tiers: Vec<PartnerTier>,
+    // total amount of purchases referred by the partner
+    pub volume: Balance,
+}
+
+#[derive(BorshSerialize, BorshDeserialize)]
+pub struct PartnerTier {
+    pub min_volume: Balance,
+    pub fee: FeeFraction,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct PartnerTierInput {
+    pub min_volume: U128,
+    pub fee: FeeFraction,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct PartnerOutput {
+    pub fee: FeeFraction,
+    pub tiers: Vec<PartnerTierInput>,
+    pub volume: U128,
+    pub current_fee: FeeFraction,
+}
+
+impl Partner {
+    // fee for the next purchase based on the volume referred so far
+    fn get_fee(&self) -> FeeFraction {
+        self.tiers
+            .iter()
+            .filter(|tier| tier.min_volume <= self.volume)
+            .max_by_key(|tier| tier.min_volume)
+            .map(|tier| tier.fee.clone())
+            .unwrap_or_else(|| self.fee.clone())
+    }
+}
+
+impl From<Partner> for PartnerOutput {
+    fn from(partner: Partner) -> Self {
+        let current_fee = partner.get_fee();
+        PartnerOutput {
+            fee: partner.fee,
+            tiers: partner
+                .tiers
+                .into_iter()
+                .map(|tier| PartnerTierInput {
+                    min_volume: U128::from(tier.min_volume),
+                    fee: tier.fee,
+                })
+                .collect(),
+            volume: U128::from(partner.volume),
+            current_fee,
+        }
+    }
+}
+
+#[near_bindgen]
+impl SocialPremium {
+    pub fn set_partner(
+        &mut self,
+        account_id: AccountId,
+        fee: FeeFraction,
+        tiers: Vec<PartnerTierInput>,
+    ) {
+        self.assert_owner();
+
+        fee.assert_valid();
+        for tier in &tiers {
+            tier.fee.assert_valid();
+        }
+
+        // the referred volume is kept when partner terms are updated
+        let volume = self
+            .partners
+            .get(&account_id)
+            .map(|partner| partner.volume)
+            .unwrap_or(0);
+
+        self.partners.insert(
+            &account_id,
+            &Partner {
+                fee,
+                tiers: tiers
+                    .into_iter()
+                    .map(|tier| PartnerTier {
+                        min_volume: tier.min_volume.0,
+                        fee: tier.fee,
+                    })
+                    .collect(),
+                volume,
+            },
+        );
+        self.assert_referral_fees_valid();
+    }
+
+    pub fn remove_partner(&mut self, account_id: AccountId) {
+        self.assert_owner();
+        self.partners.remove(&account_id).expect("ERR_NO_PARTNER");
+    }
+
+    pub fn get_partner(&self, account_id: AccountId) -> Option<PartnerOutput> {
+        self.partners.get(&account_id).map(|partner| partner.into())
+    }
+}
+
+impl SocialPremium {
+    // returns the fee of the partner and adds the purchase to the partner volume
+    pub(crate) fn internal_use_partner_fee(
+        &mut self,
+        account_id: &AccountId,
+        amount: Balance,
+    ) -> Option<FeeFraction> {
+        self.partners.get(account_id).map(|mut partner| {
+            let fee = partner.get_fee();
+            partner.volume += amount;
+            self.partners.insert(account_id, &partner);
+            fee
+        })
+    }
+
+    // removes the refunded part of a referred purchase from the partner volume
+    pub(crate) fn internal_reduce_partner_volume(
+        &mut self,
+        account_id: &AccountId,
+        amount: Balance,
+    ) {
+        if let Some(mut partner) = self.partners.get(account_id) {
+            partner.volume = partner.volume.saturating_sub(amount);
+            self.partners.insert(account_id, &partner);
+        }
+    }
+}
@@ -0,0 +1,702 @@
+use crate::*;
+
+use near_sdk::is_promise_success;
+
+pub const GAS_FOR_AFTER_REFERRAL_REWARDS_CLAIM: Gas = Gas(Gas::ONE_TERA.0 * 10);
+// max number of pending rewards vested in a single call
+pub const MAX_VESTED_REWARDS_PER_CALL: usize = 50;
+
+// operation of the purchase which generated the reward and whether it is
+// a reward of the referral of the referral
+type PendingReferralRewardKey = (u64, bool);
+
+#[derive(BorshSerialize, BorshDeserialize)]
+pub struct PendingReferralReward {
+    pub amount: Balance,
+    pub unlock_at: u128,
+}
+
+#[derive(BorshSerialize, BorshDeserialize)]
+pub struct PendingReferralRewards {
+    pub total: Balance,
+    // ordered by operation, so the oldest rewards are vested first
+    pub rewards: TreeMap<PendingReferralRewardKey, PendingReferralReward>,
+}
+
+#[derive(BorshSerialize, BorshDeserialize, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub enum ReferralAttribution {
+    // the first referral is kept until the binding expires
+    FirstTouch,
+    // the last provided referral replaces the stored one
+    LastTouch,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct ReferralAttributionOutput {
+    pub attribution: ReferralAttribution,
+    pub window_ms: Option<U128>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct ReferralBindingOutput {
+    pub referral_account_id: ReferralAccountId,
+    pub bound_at: U128,
+    pub expires_at: Option<U128>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct ReferralRewardsOutput {
+    pub pending: U128,
+    pub vested: U128,
+    pub total: U128,
+}
+
+#[near_bindgen]
+impl SocialPremium {
+    pub fn set_referral_holdback(&mut self, referral_holdback_ms: U128) {
+        self.assert_owner();
+        self.referral_holdback_ms = referral_holdback_ms.0;
+    }
+
+    pub fn set_second_level_referral_fee(&mut self, second_level_referral_fee: FeeFraction) {
+        self.assert_owner();
+        second_level_referral_fee.assert_valid();
+        self.second_level_referral_fee = second_level_referral_fee;
+        self.assert_referral_fees_valid();
+    }
+
+    pub fn set_referral_attribution(
+        &mut self,
+        attribution: ReferralAttribution,
+        window_ms: Option<U128>,
+    ) {
+        self.assert_owner();
+        self.referral_attribution = attribution;
+        self.referral_window_ms = window_ms.map(|window_ms| window_ms.0);
+    }
+
+    pub fn cancel_referral_reward(
+        &mut self,
+        referral_account_id: ReferralAccountId,
+        operation_id: u64,
+    ) -> U128 {
+        self.assert_owner();
+        U128::from(self.internal_cancel_referral_reward(&referral_account_id, operation_id))
+    }
+
+    // adds existing referrals to the affiliates index, returns the number of processed referrals
+    pub fn index_affiliates(&mut self, from_index: u64, limit: u64) -> u64 {
+        self.assert_owner();
+
+        let accounts = self.referrals.keys_as_vector();
+        let referral_accounts = self.referrals.values_as_vector();
+        let to_index = std::cmp::min(from_index + limit, accounts.len());
+
+        let referrals: Vec<(AccountId, ReferralAccountId)> = (from_index..to_index)
+            .map(|index| {
+                (
+                    accounts.get(index).expect("ERR_NO_DATA"),
+                    referral_accounts.get(index).expect("ERR_NO_DATA"),
+                )
+            })
+            .collect();
+
+        for (account_id, referral_account_id) in referrals {
+            self.internal_add_affiliate(&referral_account_id, &account_id);
+        }
+
+        to_index.saturating_sub(from_index)
+    }
+
+    pub fn claim_referral_rewards(&mut self) -> Promise {
+        let account_id = env::predecessor_account_id();
+
+        self.internal_vest_referral_rewards(&account_id);
+
+        let amount = self
+            .referral_balances
+            .remove(&account_id)
+            .expect("ERR_NO_REFERRAL_REWARDS");
+
+        log!(
+            "Referral rewards claimed by {}: {} yNEAR",
+            account_id,
+            amount
+        );
+
+        self.internal_post_ledger_entry(
+            LedgerAccount::ReferralPayouts,
+            LedgerAccount::ReferralReserve,
+            amount,
+        );
+
+        Promise::new(account_id.clone()).transfer(amount).then(
+            ext_self::ext(env::current_account_id())
+                .with_static_gas(GAS_FOR_AFTER_REFERRAL_REWARDS_CLAIM)
+                .on_referral_rewards_claimed(account_id, U128::from(amount)),
+        )
+    }
+
+    #[private]
+    pub fn on_referral_rewards_claimed(&mut self, account_id: AccountId, amount: U128) {
+        if !is_promise_success() {
+            // restore the balance if the transfer failed
+            log!("Referral rewards transfer to {} failed", account_id);
+            self.internal_increase_referral_balance(&account_id, amount.0);
+            self.internal_post_ledger_entry(
+                LedgerAccount::ReferralReserve,
+                LedgerAccount::ReferralPayouts,
+                amount.0,
+            );
+        }
+    }
+
+    pub fn get_referral_balance(&self, referral_account_id: ReferralAccountId) -> U128 {
+        self.get_referral_rewards(referral_account_id).vested
+    }
+
+    pub fn get_referral_rewards(
+        &self,
+        referral_account_id: ReferralAccountId,
+    ) -> ReferralRewardsOutput {
+        let now: u128 = env::block_timestamp_ms().into();
+
+        let (unlocked, pending) = if let Some(pending_rewards) =
+            self.pending_referral_rewards.get(&referral_account_id)
+        {
+            let unlocked = pending_rewards
+                .rewards
+                .iter()
+                .take_while(|(_, reward)| reward.unlock_at <= now)
+                .map(|(_, reward)| reward.amount)
+                .sum::<Balance>();
+            (unlocked, pending_rewards.total - unlocked)
+        } else {
+            (0, 0)
+        };
+
+        let vested = self
+            .referral_balances
+            .get(&referral_account_id)
+            .unwrap_or_default()
+            + unlocked;
+
+        ReferralRewardsOutput {
+            pending: U128::from(pending),
+            vested: U128::from(vested),
+            total: U128::from(
+                self.referral_rewards
+                    .get(&referral_account_id)
+                    .unwrap_or_default()
+                    + self
+                        .second_level_referral_rewards
+                        .get(&referral_account_id)
+                        .unwrap_or_default(),
+            ),
+        }
+    }
+
+    pub fn get_second_level_referral_fee(&self) -> FeeFraction {
+        self.second_level_referral_fee.clone()
+    }
+
+    pub fn get_second_level_referral_reward(&self, referral_account_id: ReferralAccountId) -> U128 {
+        U128::from(
+            self.second_level_referral_rewards
+                .get(&referral_account_id)
+                .unwrap_or_default(),
+        )
+    }
+
+    pub fn get_referral_attribution(&self) -> ReferralAttributionOutput {
+        ReferralAttributionOutput {
+            attribution: self.referral_attribution.clone(),
+            window_ms: self.referral_window_ms.map(U128::from),
+        }
+    }
+
+    pub fn get_referral_binding(&self, account_id: AccountId) -> Option<ReferralBindingOutput> {
+        self.referrals.get(&account_id).map(|referral_account_id| {
+            let bound_at = self.internal_get_referral_bound_at(&account_id);
+            ReferralBindingOutput {
+                referral_account_id,
+                bound_at: U128::from(bound_at),
+                expires_at: self
+                    .referral_window_ms
+                    .map(|window_ms| U128::from(bound_at + window_ms)),
+            }
+        })
+    }
+
+    pub fn get_referral_holdback(&self) -> U128 {
+        U128::from(self.referral_holdback_ms)
+    }
+}
+
+impl SocialPremium {
+    // stores the referral of the receiver and credits the reward to the referral balance,
+    // returns the rewards of both referral levels
+    pub(crate) fn internal_add_referral_reward(
+        &mut self,
+        receiver_id: &AccountId,
+        user_referral_id: ReferralAccountId,
+        amount: Balance,
+        referral_is_premium: bool,
+        operation_id: u64,
+    ) -> Balance {
+        self.internal_set_referral(receiver_id, &user_referral_id);
+
+        let first_purchase_at = self.internal_touch_first_referred_purchase(receiver_id);
+
+        // rejected rewards stay in the treasury
+        if let Some(reason) =
+            self.internal_check_referral_rules(receiver_id, &user_referral_id, amount)
+        {
+            log!(
+                "Referral reward for {} rejected: {}",
+                user_referral_id,
+                reason
+            );
+            self.referral_commissions.insert(
+                &operation_id,
+                &ReferralCommission {
+                    referral_account_id: user_referral_id,
+                    receiver_id: receiver_id.clone(),
+                    amount,
+                    first_purchase_at,
+                    rule: CommissionRule::Rejected { reason },
+                    fee: None,
+                    reward: 0,
+                    second_level_referral_account_id: None,
+                    second_level_reward: 0,
+                },
+            );
+            return 0;
+        }
+
+        let (rule, fee) = self.internal_get_commission_rule(
+            &user_referral_id,
+            amount,
+            referral_is_premium,
+            first_purchase_at,
+        );
+        let referral_reward = fee.multiply(amount);
+
+        log!(
+            "{}Referral reward for {}: {} yNEAR",
+            match rule {
+                CommissionRule::Partner => "Partner ",
+                CommissionRule::Renewal { .. } => "Renewal ",
+                CommissionRule::Premium => "Premium ",
+                _ => "",
+            },
+            user_referral_id,
+            referral_reward.to_string()
+        );
+
+        let recorded_reward = self.internal_record_referral_reward(
+            &user_referral_id,
+            referral_reward,
+            false,
+            operation_id,
+        );
+        self.internal_update_leaderboard(&user_referral_id, amount, recorded_reward);
+
+        // the referral of the referral gets the second level reward, cycles are skipped
+        let second_level_referral_id = self
+            .internal_get_bound_referral(&user_referral_id)
+            .filter(|referral_id| referral_id != receiver_id && referral_id != &user_referral_id);
+
+        let second_level_recorded_reward =
+            if let Some(second_level_referral_id) = second_level_referral_id.as_ref() {
+                let second_level_referral_reward = self.second_level_referral_fee.multiply(amount);
+
+                if second_level_referral_reward > 0 {
+                    log!(
+                        "Second level referral reward for {}: {} yNEAR",
+                        second_level_referral_id,
+                        second_level_referral_reward.to_string()
+                    );
+
+                    self.internal_record_referral_reward(
+                        second_level_referral_id,
+                        second_level_referral_reward,
+                        true,
+                        operation_id,
+                    )
+                } else {
+                    0
+                }
+            } else {
+                0
+            };
+
+        self.referral_commissions.insert(
+            &operation_id,
+            &ReferralCommission {
+                referral_account_id: user_referral_id,
+                receiver_id: receiver_id.clone(),
+                amount,
+                first_purchase_at,
+                rule,
+                fee: Some(fee),
+                reward: recorded_reward,
+                second_level_referral_account_id: second_level_referral_id
+                    .filter(|_| second_level_recorded_reward > 0),
+                second_level_reward: second_level_recorded_reward,
+            },
+        );
+
+        recorded_reward + second_level_recorded_reward
+    }
+
+    // the rewards of both referral levels can't exceed the purchase amount
+    pub(crate) fn assert_referral_fees_valid(&self) {
+        let mut first_level_fees =
+            vec![self.referral_fee.clone(), self.premium_referral_fee.clone()];
+        first_level_fees.extend(
+            self.renewal_commissions
+                .iter()
+                .map(|renewal_commission| renewal_commission.fee.clone()),
+        );
+        for partner in self.partners.values() {
+            first_level_fees.push(partner.fee);
+            first_level_fees.extend(partner.tiers.into_iter().map(|tier| tier.fee));
+        }
+
+        for fee in first_level_fees {
+            fee.assert_valid_sum(&self.second_level_referral_fee);
+        }
+    }
+
+    fn internal_record_referral_reward(
+        &mut self,
+        account_id: &AccountId,
+        amount: Balance,
+        second_level: bool,
+        operation_id: u64,
+    ) -> Balance {
+        let amount = self.internal_apply_referral_reward_cap(account_id, amount);
+        if amount == 0 {
+            return 0;
+        }
+
+        let rewards = if second_level {
+            &mut self.second_level_referral_rewards
+        } else {
+            &mut self.referral_rewards
+        };
+        let prev_referral_reward = rewards.get(account_id).unwrap_or_default();
+        rewards.insert(account_id, &(prev_referral_reward + amount));
+
+        self.total_referral_rewards += amount;
+        self.internal_post_ledger_entry(
+            LedgerAccount::ReferralReserve,
+            LedgerAccount::Available,
+            amount,
+        );
+
+        self.internal_add_pending_referral_reward(account_id, amount, second_level, operation_id);
+
+        amount
+    }
+
+    // rewards are claimable after the holdback period
+    fn internal_add_pending_referral_reward(
+        &mut self,
+        account_id: &AccountId,
+        amount: Balance,
+        second_level: bool,
+        operation_id: u64,
+    ) {
+        if self.referral_holdback_ms == 0 {
+            self.internal_increase_referral_balance(account_id, amount);
+            return;
+        }
+
+        self.internal_vest_referral_rewards(account_id);
+
+        let now: u128 = env::block_timestamp_ms().into();
+
+        let mut pending_rewards = self.internal_get_pending_referral_rewards(account_id);
+        pending_rewards.rewards.insert(
+            &(operation_id, second_level),
+            &PendingReferralReward {
+                amount,
+                unlock_at: now + self.referral_holdback_ms,
+            },
+        );
+        pending_rewards.total += amount;
+        self.internal_save_pending_referral_rewards(account_id, &pending_rewards);
+    }
+
+    // moves unlocked pending rewards to the claimable balance, oldest first and at most
+    // MAX_VESTED_REWARDS_PER_CALL at once, the rest is vested by the next calls
+    fn internal_vest_referral_rewards(&mut self, account_id: &AccountId) {
+        if let Some(mut pending_rewards) = self.pending_referral_rewards.get(account_id) {
+            let now: u128 = env::block_timestamp_ms().into();
+
+            let vested: Vec<(PendingReferralRewardKey, PendingReferralReward)> = pending_rewards
+                .rewards
+                .iter()
+                .take(MAX_VESTED_REWARDS_PER_CALL)
+                .take_while(|(_, reward)| reward.unlock_at <= now)
+                .collect();
+
+            if vested.is_empty() {
+                return;
+            }
+
+            for (key, _) in &vested {
+                pending_rewards.rewards.remove(key);
+            }
+
+            let amount = vested
+                .iter()
+                .map(|(_, reward)| reward.amount)
+                .sum::<Balance>();
+            pending_rewards.total -= amount;
+            self.internal_save_pending_referral_rewards(account_id, &pending_rewards);
+
+            self.internal_increase_referral_balance(account_id, amount);
+        }
+    }
+
+    fn internal_get_pending_referral_rewards(
+        &self,
+        account_id: &AccountId,
+    ) -> PendingReferralRewards {
+        self.pending_referral_rewards
+            .get(account_id)
+            .unwrap_or_else(|| PendingReferralRewards {
+                total: 0,
+                rewards: TreeMap::new(StorageKey::PendingReferralRewardsPerAccount {
+                    account_hash: env::sha256_array(account_id.as_bytes()),
+                }),
+            })
+    }
+
+    fn internal_save_pending_referral_rewards(
+        &mut self,
+        account_id: &AccountId,
+        pending_rewards: &PendingReferralRewards,
+    ) {
+        if pending_rewards.rewards.is_empty() {
+            self.pending_referral_rewards.remove(account_id);
+        } else {
+            self.pending_referral_rewards
+                .insert(account_id, pending_rewards);
+        }
+    }
+
+    // cancels the reward of the operation if it is still in the holdback period,
+    // returns the cancelled amount
+    pub(crate) fn internal_cancel_referral_reward(
+        &mut self,
+        account_id: &AccountId,
+        operation_id: u64,
+    ) -> Balance {
+        self.internal_vest_referral_rewards(account_id);
+
+        let mut pending_rewards = self.internal_get_pending_referral_rewards(account_id);
+
+        if let Some((second_level, reward)) = [false, true].iter().find_map(|second_level| {
+            pending_rewards
+                .rewards
+                .remove(&(operation_id, *second_level))
+                .map(|reward| (*second_level, reward))
+        }) {
+            pending_rewards.total -= reward.amount;
+            self.internal_save_pending_referral_rewards(account_id, &pending_rewards);
+
+            self.internal_remove_recorded_referral_reward(account_id, reward.amount, second_level);
+
+            log!(
+                "Referral reward of {} for operation {} cancelled: {} yNEAR",
+                account_id,
+                operation_id,
+                reward.amount
+            );
+
+            reward.amount
+        } else {
+            0
+        }
+    }
+
+    // takes back up to the amount of the operation reward, from the pending reward
+    // if it is still in the holdback period or from the claimable balance otherwise,
+    // returns the taken amount
+    pub(crate) fn internal_claw_back_referral_reward(
+        &mut self,
+        account_id: &AccountId,
+        operation_id: u64,
+        amount: Balance,
+        second_level: bool,
+    ) -> Balance {
+        self.internal_vest_referral_rewards(account_id);
+
+        let mut pending_rewards = self.internal_get_pending_referral_rewards(account_id);
+        let key = (operation_id, second_level);
+
+        let clawed_back = if let Some(mut reward) = pending_rewards.rewards.get(&key) {
+            let clawed_back = std::cmp::min(amount, reward.amount);
+            reward.amount -= clawed_back;
+
+            if reward.amount == 0 {
+                pending_rewards.rewards.remove(&key);
+            } else {
+                pending_rewards.rewards.insert(&key, &reward);
+            }
+            pending_rewards.total -= clawed_back;
+            self.internal_save_pending_referral_rewards(account_id, &pending_rewards);
+
+            clawed_back
+        } else {
+            // claimed rewards can't be taken back
+            let balance = self.referral_balances.get(account_id).unwrap_or_default();
+            let clawed_back = std::cmp::min(amount, balance);
+
+            if clawed_back == balance {
+                self.referral_balances.remove(account_id);
+            } else {
+                self.referral_balances
+                    .insert(account_id, &(balance - clawed_back));
+            }
+
+            clawed_back
+        };
+
+        if clawed_back > 0 {
+            self.internal_remove_recorded_referral_reward(account_id, clawed_back, second_level);
+
+            log!(
+                "Referral reward of {} for operation {} clawed back: {} yNEAR",
+                account_id,
+                operation_id,
+                clawed_back
+            );
+        }
+
+        clawed_back
+    }
+
+    fn internal_remove_recorded_referral_reward(
+        &mut self,
+        account_id: &AccountId,
+        amount: Balance,
+        second_level: bool,
+    ) {
+        let rewards = if second_level {
+            &mut self.second_level_referral_rewards
+        } else {
+            &mut self.referral_rewards
+        };
+        let total_reward = rewards.get(account_id).unwrap_or_default();
+        rewards.insert(account_id, &total_reward.saturating_sub(amount));
+        self.total_referral_rewards = self.total_referral_rewards.saturating_sub(amount);
+        self.internal_post_ledger_entry(
+            LedgerAccount::Available,
+            LedgerAccount::ReferralReserve,
+            amount,
+        );
+    }
+
+    // chooses the referral of the purchase according to the attribution policy
+    pub(crate) fn internal_get_referral(
+        &self,
+        receiver_id: &AccountId,
+        referral_id: Option<ReferralAccountId>,
+    ) -> Option<ReferralAccountId> {
+        if let Some(referral_id) = referral_id.as_ref() {
+            // referral id was provided in the request
+            assert!(referral_id != receiver_id, "ERR_SELF_REFERRAL_NOT_ALLOWED");
+        }
+
+        // previously stored referral id
+        let stored_referral_id = self.internal_get_bound_referral(receiver_id);
+
+        match self.referral_attribution {
+            ReferralAttribution::FirstTouch => stored_referral_id.or(referral_id),
+            ReferralAttribution::LastTouch => referral_id.or(stored_referral_id),
+        }
+    }
+
+    // returns the stored referral if the binding has not expired
+    fn internal_get_bound_referral(&self, account_id: &AccountId) -> Option<ReferralAccountId> {
+        self.referrals.get(account_id).filter(|_| {
+            if let Some(window_ms) = self.referral_window_ms {
+                let now: u128 = env::block_timestamp_ms().into();
+                self.internal_get_referral_bound_at(account_id) + window_ms > now
+            } else {
+                true
+            }
+        })
+    }
+
+    fn internal_get_referral_bound_at(&self, account_id: &AccountId) -> u128 {
+        self.referral_bindings
+            .get(account_id)
+            .unwrap_or(self.legacy_referrals_bound_at)
+    }
+
+    // stores the referral of the account and keeps the affiliates index consistent
+    pub(crate) fn internal_set_referral(
+        &mut self,
+        account_id: &AccountId,
+        referral_account_id: &ReferralAccountId,
+    ) {
+        // the binding time is kept while the same referral stays bound
+        if self.internal_get_bound_referral(account_id).as_ref() != Some(referral_account_id) {
+            let now: u128 = env::block_timestamp_ms().into();
+            self.referral_bindings.insert(account_id, &now);
+        }
+
+        if let Some(prev_referral_account_id) =
+            self.referrals.insert(account_id, referral_account_id)
+        {
+            if &prev_referral_account_id == referral_account_id {
+                return;
+            }
+
+            if let Some(mut affiliates) = self.affiliates.get(&prev_referral_account_id) {
+                affiliates.remove(account_id);
+                if affiliates.is_empty() {
+                    self.affiliates.remove(&prev_referral_account_id);
+                } else {
+                    self.affiliates
+                        .insert(&prev_referral_account_id, &affiliates);
+                }
+            }
+        }
+
+        self.internal_add_affiliate(referral_account_id, account_id);
+    }
+
+    fn internal_add_affiliate(
+        &mut self,
+        referral_account_id: &ReferralAccountId,
+        account_id: &AccountId,
+    ) {
+        let mut affiliates = self.affiliates.get(referral_account_id).unwrap_or_else(|| {
+            UnorderedSet::new(StorageKey::AffiliatesPerReferral {
+                account_hash: env::sha256_array(referral_account_id.as_bytes()),
+            })
+        });
+        if affiliates.insert(account_id) {
+            self.affiliates.insert(referral_account_id, &affiliates);
+        }
+    }
+
+    fn internal_increase_referral_balance(&mut self, account_id: &AccountId, amount: Balance) {
+        if amount > 0 {
+            let balance = self.referral_balances.get(account_id).unwrap_or_default();
+            self.referral_balances
+                .insert(account_id, &(balance + amount));
+        }
+    }
+}
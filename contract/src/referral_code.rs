@@ -0,0 +1,133 @@
+use crate::*;
+
+const MIN_REFERRAL_CODE_LENGTH: usize = 3;
+const MAX_REFERRAL_CODE_LENGTH: usize = 32;
+
+#[near_bindgen]
+impl SocialPremium {
+    #[payable]
+    pub fn register_referral_code(&mut self, code: ReferralCode) {
+        let initial_storage = env::storage_usage();
+        let account_id = env::predecessor_account_id();
+
+        assert_valid_referral_code(&code);
+        assert!(
+            self.referral_codes.insert(&code, &account_id).is_none(),
+            "ERR_REFERRAL_CODE_TAKEN"
+        );
+
+        log!("Referral code {} registered by {}", code, account_id);
+
+        charge_storage_deposit(initial_storage);
+    }
+
+    #[payable]
+    pub fn transfer_referral_code(&mut self, code: ReferralCode, receiver_id: AccountId) {
+        assert_eq!(env::attached_deposit(), ONE_YOCTO, "ERR_ONE_YOCTO_REQUIRED");
+
+        self.assert_referral_code_owner(&code);
+        self.referral_codes.insert(&code, &receiver_id);
+
+        log!("Referral code {} transferred to {}", code, receiver_id);
+    }
+
+    #[payable]
+    pub fn release_referral_code(&mut self, code: ReferralCode) {
+        assert_eq!(env::attached_deposit(), ONE_YOCTO, "ERR_ONE_YOCTO_REQUIRED");
+
+        let initial_storage = env::storage_usage();
+
+        let account_id = self.assert_referral_code_owner(&code);
+        self.referral_codes.remove(&code);
+
+        log!("Referral code {} released by {}", code, account_id);
+
+        refund_released_storage(initial_storage, account_id);
+    }
+
+    pub fn get_referral_code(&self, code: ReferralCode) -> Option<AccountId> {
+        self.referral_codes.get(&code)
+    }
+}
+
+impl SocialPremium {
+    fn assert_referral_code_owner(&self, code: &ReferralCode) -> AccountId {
+        let account_id = env::predecessor_account_id();
+        assert_eq!(
+            self.referral_codes.get(code).expect("ERR_NO_REFERRAL_CODE"),
+            account_id,
+            "ERR_NO_ACCESS"
+        );
+        account_id
+    }
+
+    // referral can be provided either as an account id or as a registered code
+    pub(crate) fn internal_resolve_referral(
+        &self,
+        referral_id: Option<ReferralAccountId>,
+        referral_code: Option<ReferralCode>,
+    ) -> Option<ReferralAccountId> {
+        if let Some(referral_code) = referral_code {
+            assert!(referral_id.is_none(), "ERR_REFERRAL_ID_AND_CODE");
+            Some(
+                self.referral_codes
+                    .get(&referral_code)
+                    .expect("ERR_NO_REFERRAL_CODE"),
+            )
+        } else {
+            referral_id
+        }
+    }
+}
+
+fn assert_valid_referral_code(code: &str) {
+    assert!(
+        (MIN_REFERRAL_CODE_LENGTH..=MAX_REFERRAL_CODE_LENGTH).contains(&code.len()),
+        "ERR_INVALID_REFERRAL_CODE_LENGTH"
+    );
+    assert!(
+        code.bytes()
+            .all(|c| c.is_ascii_alphanumeric() || c == b'_' || c == b'-'),
+        "ERR_INVALID_REFERRAL_CODE"
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::*;
+
+    const STORAGE_DEPOSIT: Balance = ONE_NEAR / 100;
+
+    #[test]
+    fn test_lowercase_referral_code() {
+        let mut contract = setup_contract(None);
+
+        set_context(alice(), NOW_MS, STORAGE_DEPOSIT);
+        contract.register_referral_code("alice2024".to_string());
+
+        assert_eq!(
+            contract.internal_resolve_referral(None, Some("alice2024".to_string())),
+            Some(alice())
+        );
+        // an account id with the same name as a code is not resolved as the code
+        assert_eq!(
+            contract.internal_resolve_referral(
+                Some(AccountId::new_unchecked("alice2024".to_string())),
+                None
+            ),
+            Some(AccountId::new_unchecked("alice2024".to_string()))
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "ERR_REFERRAL_ID_AND_CODE")]
+    fn test_referral_id_and_code() {
+        let mut contract = setup_contract(None);
+
+        set_context(alice(), NOW_MS, STORAGE_DEPOSIT);
+        contract.register_referral_code("alice2024".to_string());
+
+        contract.internal_resolve_referral(Some(bob()), Some("alice2024".to_string()));
+    }
+}
@@ -0,0 +1,147 @@
+use crate::*;
+
+#[derive(BorshSerialize, BorshDeserialize, Default)]
+pub struct ReferralRules {
+    // purchases below this amount are not rewarded
+    pub min_purchase_amount: Balance,
+    // number of referral hops checked for cycles, disabled if 0
+    pub max_cycle_depth: u8,
+    // max rewards of a referral per period, unlimited if None
+    pub reward_cap: Option<Balance>,
+    pub reward_cap_period_ms: u128,
+    // min time between rewarded purchases of the same referral and receiver
+    pub pair_cooldown_ms: u128,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct ReferralRulesInput {
+    pub min_purchase_amount: U128,
+    pub max_cycle_depth: u8,
+    pub reward_cap: Option<U128>,
+    pub reward_cap_period_ms: U128,
+    pub pair_cooldown_ms: U128,
+}
+
+impl From<ReferralRulesInput> for ReferralRules {
+    fn from(rules: ReferralRulesInput) -> Self {
+        ReferralRules {
+            min_purchase_amount: rules.min_purchase_amount.0,
+            max_cycle_depth: rules.max_cycle_depth,
+            reward_cap: rules.reward_cap.map(|reward_cap| reward_cap.0),
+            reward_cap_period_ms: rules.reward_cap_period_ms.0,
+            pair_cooldown_ms: rules.pair_cooldown_ms.0,
+        }
+    }
+}
+
+impl From<&ReferralRules> for ReferralRulesInput {
+    fn from(rules: &ReferralRules) -> Self {
+        ReferralRulesInput {
+            min_purchase_amount: U128::from(rules.min_purchase_amount),
+            max_cycle_depth: rules.max_cycle_depth,
+            reward_cap: rules.reward_cap.map(U128::from),
+            reward_cap_period_ms: U128::from(rules.reward_cap_period_ms),
+            pair_cooldown_ms: U128::from(rules.pair_cooldown_ms),
+        }
+    }
+}
+
+#[near_bindgen]
+impl SocialPremium {
+    pub fn set_referral_rules(&mut self, referral_rules: ReferralRulesInput) {
+        self.assert_owner();
+
+        if referral_rules.reward_cap.is_some() {
+            assert!(
+                referral_rules.reward_cap_period_ms.0 > 0,
+                "ERR_ZERO_REWARD_CAP_PERIOD"
+            );
+        }
+
+        self.referral_rules = referral_rules.into();
+    }
+
+    pub fn get_referral_rules(&self) -> ReferralRulesInput {
+        (&self.referral_rules).into()
+    }
+}
+
+impl SocialPremium {
+    // returns the reason to reject the referral reward of the purchase
+    pub(crate) fn internal_check_referral_rules(
+        &mut self,
+        receiver_id: &AccountId,
+        referral_account_id: &ReferralAccountId,
+        amount: Balance,
+    ) -> Option<String> {
+        if amount < self.referral_rules.min_purchase_amount {
+            return Some("purchase amount is below the minimum".to_string());
+        }
+
+        // receiver is a referral of the referral chain
+        let mut account_id = referral_account_id.clone();
+        for _ in 0..self.referral_rules.max_cycle_depth {
+            match self.referrals.get(&account_id) {
+                Some(next_account_id) if &next_account_id == receiver_id => {
+                    return Some("referral cycle detected".to_string());
+                }
+                Some(next_account_id) => account_id = next_account_id,
+                None => break,
+            }
+        }
+
+        let now: u128 = env::block_timestamp_ms().into();
+        let pair = (referral_account_id.clone(), receiver_id.clone());
+
+        if self.referral_rules.pair_cooldown_ms > 0 {
+            if let Some(rewarded_at) = self.referral_pair_rewarded_at.get(&pair) {
+                if rewarded_at + self.referral_rules.pair_cooldown_ms > now {
+                    return Some("referral and receiver are in cooldown".to_string());
+                }
+            }
+
+            self.referral_pair_rewarded_at.insert(&pair, &now);
+        }
+
+        None
+    }
+
+    // limits the reward by the cap of the current period
+    pub(crate) fn internal_apply_referral_reward_cap(
+        &mut self,
+        referral_account_id: &ReferralAccountId,
+        reward: Balance,
+    ) -> Balance {
+        if let Some(reward_cap) = self.referral_rules.reward_cap {
+            let now: u128 = env::block_timestamp_ms().into();
+            let period = (now / self.referral_rules.reward_cap_period_ms) as u64;
+
+            let period_rewards = match self.referral_period_rewards.get(referral_account_id) {
+                Some((rewards_period, period_rewards)) if rewards_period == period => {
+                    period_rewards
+                }
+                _ => 0,
+            };
+
+            let capped_reward = std::cmp::min(reward, reward_cap.saturating_sub(period_rewards));
+
+            if capped_reward < reward {
+                log!(
+                    "Referral reward for {} capped: {} yNEAR returned to treasury",
+                    referral_account_id,
+                    reward - capped_reward
+                );
+            }
+
+            self.referral_period_rewards.insert(
+                referral_account_id,
+                &(period, period_rewards + capped_reward),
+            );
+
+            capped_reward
+        } else {
+            reward
+        }
+    }
+}
@@ -0,0 +1,535 @@
+use crate::*;
+
+use near_sdk::is_promise_success;
+use near_sdk::serde_json::Value;
+
+pub const GAS_FOR_AFTER_REFUND_SOCIAL_GET: Gas = Gas(Gas::ONE_TERA.0 * 150);
+pub const GAS_FOR_AFTER_REFUND_TRANSFER: Gas = Gas(Gas::ONE_TERA.0 * 110);
+
+#[derive(BorshSerialize, BorshDeserialize)]
+pub struct RefundPolicy {
+    // time after the purchase when a refund can be requested, refunds are disabled if 0
+    pub window_ms: u128,
+    // refund only the unused part of the purchased period
+    pub prorated: bool,
+    pub fee: FeeFraction,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct RefundPolicyInput {
+    pub window_ms: U128,
+    pub prorated: bool,
+    pub fee: FeeFraction,
+}
+
+impl Default for RefundPolicy {
+    fn default() -> Self {
+        RefundPolicy {
+            window_ms: 0,
+            prorated: false,
+            fee: FeeFraction {
+                numerator: 0,
+                denominator: 1,
+            },
+        }
+    }
+}
+
+// the last purchase of the account, only it can be refunded
+#[derive(BorshSerialize, BorshDeserialize)]
+pub struct RefundablePurchase {
+    pub operation_id: u64,
+    pub payer_id: AccountId,
+    pub amount: Balance,
+    pub purchased_at: u128,
+    pub period_ms: u128,
+    pub paid_until: u128,
+    // revenue shares accrued from the purchase
+    pub revenue_shares: Vec<(AccountId, Balance)>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct RefundablePurchaseOutput {
+    pub operation_id: u64,
+    pub payer_id: AccountId,
+    pub amount: U128,
+    pub purchased_at: U128,
+    pub period_ms: U128,
+    pub paid_until: U128,
+    pub refund_until: U128,
+}
+
+#[near_bindgen]
+impl SocialPremium {
+    pub fn set_refund_policy(&mut self, refund_policy: RefundPolicyInput) {
+        self.assert_owner();
+        refund_policy.fee.assert_valid();
+        self.refund_policy = RefundPolicy {
+            window_ms: refund_policy.window_ms.0,
+            prorated: refund_policy.prorated,
+            fee: refund_policy.fee,
+        };
+    }
+
+    pub fn get_refund_policy(&self) -> RefundPolicyInput {
+        RefundPolicyInput {
+            window_ms: U128::from(self.refund_policy.window_ms),
+            prorated: self.refund_policy.prorated,
+            fee: self.refund_policy.fee.clone(),
+        }
+    }
+
+    pub fn get_refundable_purchase(
+        &self,
+        account_id: AccountId,
+        name: SubscriptionName,
+    ) -> Option<RefundablePurchaseOutput> {
+        self.refundable_purchases
+            .get(&(account_id, name))
+            .map(|purchase| RefundablePurchaseOutput {
+                operation_id: purchase.operation_id,
+                payer_id: purchase.payer_id,
+                amount: U128::from(purchase.amount),
+                purchased_at: U128::from(purchase.purchased_at),
+                period_ms: U128::from(purchase.period_ms),
+                paid_until: U128::from(purchase.paid_until),
+                refund_until: U128::from(purchase.purchased_at + self.refund_policy.window_ms),
+            })
+    }
+
+    // refunds the last purchase of the caller made by the caller
+    pub fn request_refund(&mut self, name: SubscriptionName) -> Promise {
+        let account_id = env::predecessor_account_id();
+
+        assert!(self.refund_policy.window_ms > 0, "ERR_REFUNDS_DISABLED");
+
+        let purchase = self
+            .refundable_purchases
+            .get(&(account_id.clone(), name.clone()))
+            .expect("ERR_NO_REFUNDABLE_PURCHASE");
+        assert_eq!(purchase.payer_id, account_id, "ERR_NO_ACCESS");
+
+        let now: u128 = env::block_timestamp_ms().into();
+        assert!(
+            purchase.purchased_at + self.refund_policy.window_ms >= now,
+            "ERR_REFUND_WINDOW_EXPIRED"
+        );
+
+        self.internal_refund(name, account_id, None)
+    }
+
+    // refunds the last purchase of the account regardless of the refund window
+    pub fn issue_refund(
+        &mut self,
+        name: SubscriptionName,
+        account_id: AccountId,
+        amount: Option<U128>,
+    ) -> Promise {
+        self.assert_owner();
+        self.internal_refund(name, account_id, amount)
+    }
+
+    // the refund and the deposit of the badge update are reserved in the ledger
+    // until the transfer to the payer succeeds
+    #[private]
+    pub fn refund_after_social_get(
+        &mut self,
+        #[callback_result] value: Result<Value, PromiseError>,
+        account_id: AccountId,
+        subscription_name: SubscriptionName,
+        amount: Option<U128>,
+    ) {
+        if let Ok(value) = value {
+            let purchase = self
+                .refundable_purchases
+                .get(&(account_id.clone(), subscription_name.clone()))
+                .expect("ERR_NO_REFUNDABLE_PURCHASE");
+
+            let accounts = get_accounts_data(&value, &subscription_name);
+
+            let now: u128 = env::block_timestamp_ms().into();
+            let paid_until = get_paid_until(accounts, &account_id).unwrap_or(now);
+
+            // time transferred away after the purchase is not refunded
+            let unused_ms = purchase
+                .paid_until
+                .saturating_sub(std::cmp::max(now, purchase.paid_until - purchase.period_ms));
+            let unused_refunded_ms = std::cmp::min(unused_ms, paid_until.saturating_sub(now));
+
+            let prorated_refund = if self.refund_policy.prorated {
+                prorate(purchase.amount, unused_refunded_ms, purchase.period_ms)
+            } else {
+                prorate(purchase.amount, unused_refunded_ms, unused_ms)
+            };
+
+            // custom amounts take back the time in proportion to the prorated refund
+            let (refund, refunded_ms) = if let Some(amount) = amount {
+                assert!(amount.0 <= purchase.amount, "ERR_REFUND_EXCEEDS_PURCHASE");
+                let refunded_ms = std::cmp::min(
+                    prorate(unused_refunded_ms, amount.0, prorated_refund),
+                    unused_refunded_ms,
+                );
+                (amount.0, refunded_ms)
+            } else {
+                (
+                    prorated_refund - self.refund_policy.fee.multiply(prorated_refund),
+                    unused_refunded_ms,
+                )
+            };
+            assert!(refund > 0, "ERR_NOTHING_TO_REFUND");
+
+            assert!(
+                self.ledger.get_available() >= refund + DEPOSIT_FOR_SOCIAL_SET,
+                "ERR_NOT_ENOUGH_DEPOSITS"
+            );
+            self.internal_post_ledger_entry(
+                LedgerAccount::Refunds,
+                LedgerAccount::Available,
+                refund,
+            );
+            self.internal_reserve_social_set_deposit();
+
+            let expires_at = std::cmp::max(paid_until - refunded_ms, now);
+
+            Promise::new(purchase.payer_id)
+                .transfer(refund)
+                .then(
+                    ext_self::ext(env::current_account_id())
+                        .with_static_gas(GAS_FOR_AFTER_REFUND_TRANSFER)
+                        .on_refund_transferred(
+                            account_id,
+                            subscription_name,
+                            purchase.operation_id,
+                            U128::from(refund),
+                            U128::from(refunded_ms),
+                            U128::from(expires_at),
+                        ),
+                )
+                .as_return();
+        }
+    }
+
+    // the refund is already transferred, so nothing here may panic
+    #[private]
+    pub fn on_refund_transferred(
+        &mut self,
+        account_id: AccountId,
+        subscription_name: SubscriptionName,
+        operation_id: u64,
+        refund: U128,
+        refunded_ms: U128,
+        expires_at: U128,
+    ) {
+        let refund = refund.0;
+
+        if !is_promise_success() {
+            // the purchase stays refundable and the reservations return to the treasury
+            log!("Refund transfer for {} failed", account_id);
+            self.internal_post_ledger_entry(
+                LedgerAccount::Available,
+                LedgerAccount::Refunds,
+                refund,
+            );
+            self.internal_post_ledger_entry(
+                LedgerAccount::Available,
+                LedgerAccount::StorageSpend,
+                DEPOSIT_FOR_SOCIAL_SET,
+            );
+            return;
+        }
+
+        self.deposits = self.deposits.saturating_sub(refund);
+        self.operations += 1;
+
+        // the account is locked during the refund, so the purchase can't be replaced
+        let key = (account_id.clone(), subscription_name.clone());
+        let payer_id = match self
+            .refundable_purchases
+            .get(&key)
+            .filter(|purchase| purchase.operation_id == operation_id)
+        {
+            Some(purchase) => {
+                self.refundable_purchases.remove(&key);
+                let referral_clawback = self.internal_claw_back_purchase(&purchase, refund);
+                self.internal_add_refund_stats(&subscription_name, refund, referral_clawback);
+                purchase.payer_id
+            }
+            None => {
+                log!("Refunded purchase of {} not found", account_id);
+                self.internal_add_refund_stats(&subscription_name, refund, 0);
+                account_id.clone()
+            }
+        };
+
+        log!(
+            "Refund of {} for {} to {}: {} yNEAR",
+            subscription_name,
+            account_id,
+            payer_id,
+            refund
+        );
+
+        self.internal_add_history_receipt(
+            &account_id,
+            HistoryKind::Refund,
+            &subscription_name,
+            refund,
+            refunded_ms.0,
+            expires_at.0,
+            None,
+            Some(payer_id).filter(|payer_id| payer_id != &account_id),
+        );
+
+        self.internal_write_subscription_holder(
+            subscription_name,
+            vec![SubscriptionData {
+                receiver_id: account_id,
+                timestamp: expires_at.0,
+            }],
+        )
+        .as_return();
+    }
+}
+
+impl SocialPremium {
+    // takes back referral rewards, revenue shares and referred volumes of the purchase
+    // in proportion to the refund, returns the clawed back referral rewards
+    fn internal_claw_back_purchase(
+        &mut self,
+        purchase: &RefundablePurchase,
+        refund: Balance,
+    ) -> Balance {
+        let mut referral_clawback = 0;
+        if let Some(commission) = self.referral_commissions.get(&purchase.operation_id) {
+            let first_level_clawback = self.internal_claw_back_referral_reward(
+                &commission.referral_account_id,
+                purchase.operation_id,
+                prorate(commission.reward, refund, purchase.amount),
+                false,
+            );
+            referral_clawback += first_level_clawback;
+
+            if let Some(second_level_referral_account_id) =
+                commission.second_level_referral_account_id
+            {
+                referral_clawback += self.internal_claw_back_referral_reward(
+                    &second_level_referral_account_id,
+                    purchase.operation_id,
+                    prorate(commission.second_level_reward, refund, purchase.amount),
+                    true,
+                );
+            }
+
+            if !matches!(commission.rule, CommissionRule::Rejected { .. }) {
+                let refunded_volume = prorate(commission.amount, refund, purchase.amount);
+                self.internal_reduce_leaderboard(
+                    &commission.referral_account_id,
+                    purchase.purchased_at,
+                    refunded_volume,
+                    first_level_clawback,
+                );
+                if let CommissionRule::Partner = commission.rule {
+                    self.internal_reduce_partner_volume(
+                        &commission.referral_account_id,
+                        refunded_volume,
+                    );
+                }
+            }
+        }
+
+        let revenue_shares: Vec<(AccountId, Balance)> = purchase
+            .revenue_shares
+            .iter()
+            .map(|(beneficiary_id, amount)| {
+                (
+                    beneficiary_id.clone(),
+                    prorate(*amount, refund, purchase.amount),
+                )
+            })
+            .collect();
+        self.internal_reverse_revenue_shares(&revenue_shares);
+
+        referral_clawback
+    }
+
+    fn internal_refund(
+        &mut self,
+        name: SubscriptionName,
+        account_id: AccountId,
+        amount: Option<U128>,
+    ) -> Promise {
+        self.assert_subscription(&name);
+        self.assert_not_shared(&name);
+        self.assert_not_seated(&name, &account_id);
+        assert!(
+            self.refundable_purchases
+                .get(&(account_id.clone(), name.clone()))
+                .is_some(),
+            "ERR_NO_REFUNDABLE_PURCHASE"
+        );
+        self.internal_vacate_expired_seat(&name, &account_id);
+
+        self.lock_account(&account_id);
+
+        let keys: Vec<String> = vec![format!(
+            "{}/badge/{}/accounts/{}",
+            SOCIAL_PREMIUM_ACCOUNT_ID, name, account_id
+        )];
+
+        ext_social::ext(AccountId::new_unchecked(SOCIAL_DB_ACCOUNT_ID.to_string()))
+            .with_static_gas(GAS_FOR_SOCIAL_GET)
+            .get(keys, None)
+            .then(
+                ext_self::ext(env::current_account_id())
+                    .with_static_gas(GAS_FOR_AFTER_REFUND_SOCIAL_GET)
+                    .refund_after_social_get(account_id.clone(), name, amount),
+            )
+            .then(
+                ext_self::ext(env::current_account_id())
+                    .with_static_gas(GAS_FOR_UNLOCK)
+                    .unlock_accounts(vec![account_id]),
+            )
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    pub(crate) fn internal_record_refundable_purchase(
+        &mut self,
+        subscription_name: &SubscriptionName,
+        receiver_id: &AccountId,
+        payer_id: &AccountId,
+        amount: Balance,
+        period_ms: u128,
+        paid_until: u128,
+        revenue_shares: Vec<(AccountId, Balance)>,
+    ) {
+        self.refundable_purchases.insert(
+            &(receiver_id.clone(), subscription_name.clone()),
+            &RefundablePurchase {
+                operation_id: self.operations,
+                payer_id: payer_id.clone(),
+                amount,
+                purchased_at: env::block_timestamp_ms().into(),
+                period_ms,
+                paid_until,
+                revenue_shares,
+            },
+        );
+    }
+}
+
+fn prorate(value: Balance, numerator: u128, denominator: u128) -> Balance {
+    if denominator == 0 {
+        return 0;
+    }
+    (U256::from(value) * U256::from(numerator) / U256::from(denominator)).as_u128()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::*;
+    use near_sdk::PromiseResult;
+
+    fn setup_refund(treasury: Balance) -> (SocialPremium, u64) {
+        let mut contract = setup_contract(None);
+        contract.set_refund_policy(RefundPolicyInput {
+            window_ms: U128::from(DAY_IN_MS),
+            prorated: false,
+            fee: fee(0, 1),
+        });
+
+        if treasury > 0 {
+            purchase(&mut contract, bob(), treasury, None, NOW_MS);
+        }
+        purchase(&mut contract, alice(), ONE_NEAR, None, NOW_MS);
+
+        let operation_id = contract
+            .get_refundable_purchase(alice(), NAME.to_string())
+            .unwrap()
+            .operation_id;
+        (contract, operation_id)
+    }
+
+    fn refund_after_social_get(contract: &mut SocialPremium) {
+        set_callback_context(NOW_MS, PromiseResult::Successful(vec![]));
+        contract.refund_after_social_get(
+            Ok(get_badges(contract, &[alice()])),
+            alice(),
+            NAME.to_string(),
+            None,
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "ERR_NOT_ENOUGH_DEPOSITS")]
+    fn test_refund_requires_badge_deposit() {
+        // the treasury covers the refund, but not the badge update after it
+        let (mut contract, _) = setup_refund(0);
+        contract.ledger.available.debit += 2 * DEPOSIT_FOR_SOCIAL_SET + DEPOSIT_FOR_SOCIAL_SET / 2;
+        assert!(contract.get_ledger().available.0 > ONE_NEAR);
+
+        refund_after_social_get(&mut contract);
+    }
+
+    #[test]
+    fn test_refund_after_treasury_withdrawal() {
+        let (mut contract, operation_id) = setup_refund(ONE_NEAR);
+
+        refund_after_social_get(&mut contract);
+        let storage_spend = contract.get_ledger().storage_spend.0;
+
+        // the treasury is withdrawn while the refund transfer is in flight
+        set_context(owner(), NOW_MS, 0);
+        let available = contract.get_ledger().available.0;
+        contract.withdraw_deposits(U128::from(available), None);
+
+        set_callback_context(NOW_MS, PromiseResult::Successful(vec![]));
+        contract.on_refund_transferred(
+            alice(),
+            NAME.to_string(),
+            operation_id,
+            U128::from(ONE_NEAR),
+            U128::from(MONTH_IN_MS),
+            U128::from(NOW_MS),
+        );
+
+        let ledger = contract.get_ledger();
+        assert_eq!(ledger.available.0, 0);
+        assert_eq!(ledger.available_deficit.0, 0);
+        assert_eq!(ledger.storage_spend.0, storage_spend);
+        assert!(contract
+            .get_refundable_purchase(alice(), NAME.to_string())
+            .is_none());
+        assert_eq!(
+            contract.get_expiry(alice(), NAME.to_string()),
+            Some(U128::from(NOW_MS))
+        );
+    }
+
+    #[test]
+    fn test_failed_refund_releases_badge_deposit() {
+        let (mut contract, operation_id) = setup_refund(ONE_NEAR);
+        let ledger = contract.get_ledger();
+
+        refund_after_social_get(&mut contract);
+
+        set_callback_context(NOW_MS, PromiseResult::Failed);
+        contract.on_refund_transferred(
+            alice(),
+            NAME.to_string(),
+            operation_id,
+            U128::from(ONE_NEAR),
+            U128::from(MONTH_IN_MS),
+            U128::from(NOW_MS),
+        );
+
+        let refunded_ledger = contract.get_ledger();
+        assert_eq!(refunded_ledger.available.0, ledger.available.0);
+        assert_eq!(refunded_ledger.storage_spend.0, ledger.storage_spend.0);
+        assert!(contract
+            .get_refundable_purchase(alice(), NAME.to_string())
+            .is_some());
+    }
+}
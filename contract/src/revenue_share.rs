@@ -0,0 +1,227 @@
+use crate::*;
+
+use near_sdk::is_promise_success;
+
+pub const MAX_REVENUE_SHARES: usize = 10;
+pub const GAS_FOR_AFTER_REVENUE_SHARE_DISTRIBUTE: Gas = Gas(Gas::ONE_TERA.0 * 10);
+
+// precision used to check that the shares don't exceed the revenue
+const REVENUE_SHARES_PRECISION: Balance = 1_000_000_000_000_000_000_000_000;
+
+#[derive(BorshSerialize, BorshDeserialize, Serialize, Deserialize, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct RevenueShare {
+    pub account_id: AccountId,
+    pub share: FeeFraction,
+}
+
+#[near_bindgen]
+impl SocialPremium {
+    // the revenue not covered by the shares stays available for withdraw_deposits
+    pub fn set_revenue_shares(&mut self, revenue_shares: Vec<RevenueShare>) {
+        self.assert_owner();
+
+        assert!(
+            revenue_shares.len() <= MAX_REVENUE_SHARES,
+            "ERR_TOO_MANY_REVENUE_SHARES"
+        );
+
+        for revenue_share in &revenue_shares {
+            revenue_share.share.assert_valid();
+        }
+
+        let total_shares: Balance = revenue_shares
+            .iter()
+            .map(|revenue_share| revenue_share.share.multiply(REVENUE_SHARES_PRECISION))
+            .sum();
+        assert!(
+            total_shares <= REVENUE_SHARES_PRECISION,
+            "ERR_REVENUE_SHARES_EXCEED_REVENUE"
+        );
+
+        self.revenue_shares = revenue_shares;
+    }
+
+    pub fn get_revenue_shares(&self) -> Vec<RevenueShare> {
+        self.revenue_shares.clone()
+    }
+
+    pub fn get_revenue_share_balance(&self, account_id: AccountId) -> U128 {
+        U128::from(
+            self.revenue_share_balances
+                .get(&account_id)
+                .unwrap_or_default(),
+        )
+    }
+
+    pub fn get_revenue_share_balances(&self) -> Vec<(AccountId, U128)> {
+        self.revenue_share_balances
+            .iter()
+            .map(|(account_id, balance)| (account_id, U128::from(balance)))
+            .collect()
+    }
+
+    // pays the accrued balances of all beneficiaries, including removed ones
+    pub fn distribute(&mut self) {
+        let balances: Vec<(AccountId, Balance)> = self.revenue_share_balances.iter().collect();
+        assert!(!balances.is_empty(), "ERR_NO_REVENUE_TO_DISTRIBUTE");
+
+        for (account_id, amount) in balances {
+            self.revenue_share_balances.remove(&account_id);
+
+            self.internal_post_ledger_entry(
+                LedgerAccount::RevenueDistributions,
+                LedgerAccount::RevenueShareReserve,
+                amount,
+            );
+
+            log!("Revenue distributed to {}: {} yNEAR", account_id, amount);
+
+            Promise::new(account_id.clone()).transfer(amount).then(
+                ext_self::ext(env::current_account_id())
+                    .with_static_gas(GAS_FOR_AFTER_REVENUE_SHARE_DISTRIBUTE)
+                    .on_revenue_share_distributed(account_id, U128::from(amount)),
+            );
+        }
+    }
+
+    #[private]
+    pub fn on_revenue_share_distributed(&mut self, account_id: AccountId, amount: U128) {
+        if !is_promise_success() {
+            // restore the balance if the transfer failed
+            log!("Revenue transfer to {} failed", account_id);
+            self.internal_increase_revenue_share_balance(&account_id, amount.0);
+            self.internal_post_ledger_entry(
+                LedgerAccount::RevenueShareReserve,
+                LedgerAccount::RevenueDistributions,
+                amount.0,
+            );
+        }
+    }
+}
+
+impl SocialPremium {
+    // splits the net revenue of the purchase between the beneficiaries
+    // the caller deducts the SocialDB deposit of the badge update, once per set call,
+    // so shares never take the funds needed for the storage
+    // returns the accrued amounts
+    pub(crate) fn internal_accrue_revenue_shares(
+        &mut self,
+        net_revenue: Balance,
+    ) -> Vec<(AccountId, Balance)> {
+        let mut accrued_shares = vec![];
+        for revenue_share in self.revenue_shares.clone() {
+            let amount = revenue_share.share.multiply(net_revenue);
+            if amount == 0 {
+                continue;
+            }
+
+            self.internal_increase_revenue_share_balance(&revenue_share.account_id, amount);
+            self.internal_post_ledger_entry(
+                LedgerAccount::RevenueShareReserve,
+                LedgerAccount::Available,
+                amount,
+            );
+            accrued_shares.push((revenue_share.account_id, amount));
+        }
+        accrued_shares
+    }
+
+    // takes back the accrued shares which are not distributed yet, returns the taken amount
+    pub(crate) fn internal_reverse_revenue_shares(
+        &mut self,
+        accrued_shares: &[(AccountId, Balance)],
+    ) -> Balance {
+        let mut reversed = 0;
+        for (account_id, amount) in accrued_shares {
+            let balance = self
+                .revenue_share_balances
+                .get(account_id)
+                .unwrap_or_default();
+            let reversed_share = std::cmp::min(*amount, balance);
+            if reversed_share == 0 {
+                continue;
+            }
+
+            if reversed_share == balance {
+                self.revenue_share_balances.remove(account_id);
+            } else {
+                self.revenue_share_balances
+                    .insert(account_id, &(balance - reversed_share));
+            }
+            self.internal_post_ledger_entry(
+                LedgerAccount::Available,
+                LedgerAccount::RevenueShareReserve,
+                reversed_share,
+            );
+            reversed += reversed_share;
+        }
+        reversed
+    }
+
+    fn internal_increase_revenue_share_balance(&mut self, account_id: &AccountId, amount: Balance) {
+        let balance = self
+            .revenue_share_balances
+            .get(account_id)
+            .unwrap_or_default();
+        self.revenue_share_balances
+            .insert(account_id, &(balance + amount));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::*;
+    use near_sdk::PromiseResult;
+
+    fn setup_revenue_shares() -> SocialPremium {
+        let mut contract = setup_contract(None);
+        contract.set_revenue_shares(vec![RevenueShare {
+            account_id: carol(),
+            share: fee(1, 2),
+        }]);
+        contract
+    }
+
+    #[test]
+    fn test_revenue_shares_of_purchase() {
+        let mut contract = setup_revenue_shares();
+        purchase(&mut contract, alice(), ONE_NEAR, None, NOW_MS);
+
+        assert_eq!(
+            contract.get_revenue_share_balance(carol()).0,
+            (ONE_NEAR - DEPOSIT_FOR_SOCIAL_SET) / 2
+        );
+    }
+
+    #[test]
+    fn test_revenue_shares_of_batch_purchase() {
+        let mut contract = setup_revenue_shares();
+
+        set_callback_context(NOW_MS, PromiseResult::Successful(vec![]));
+        contract.purchase_batch_after_social_get(
+            Ok(get_badges(&contract, &[alice(), bob()])),
+            NAME.to_string(),
+            vec![
+                PurchaseEntry {
+                    receiver_id: alice(),
+                    amount: U128::from(ONE_NEAR),
+                    referral_account_id: None,
+                },
+                PurchaseEntry {
+                    receiver_id: bob(),
+                    amount: U128::from(ONE_NEAR),
+                    referral_account_id: None,
+                },
+            ],
+            owner(),
+        );
+
+        // a single set writes both badges, so its deposit is deducted once
+        assert_eq!(
+            contract.get_revenue_share_balance(carol()).0,
+            (2 * ONE_NEAR - DEPOSIT_FOR_SOCIAL_SET) / 2
+        );
+    }
+}
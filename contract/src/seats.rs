@@ -0,0 +1,346 @@
+use crate::*;
+
+use near_sdk::serde_json::Value;
+
+pub const MAX_SEATS_PER_POOL: u32 = 50;
+
+#[derive(BorshSerialize, BorshDeserialize)]
+pub struct SeatPool {
+    pub seats: u32,
+    pub expires_at: u128,
+    // assigned members and their own subscription timestamps
+    pub members: HashMap<AccountId, u128>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct SeatPoolOutput {
+    pub seats: u32,
+    pub expires_at: U128,
+    pub members: Vec<AccountId>,
+}
+
+impl From<SeatPool> for SeatPoolOutput {
+    fn from(pool: SeatPool) -> Self {
+        SeatPoolOutput {
+            seats: pool.seats,
+            expires_at: U128::from(pool.expires_at),
+            members: pool.members.into_keys().collect(),
+        }
+    }
+}
+
+#[near_bindgen]
+impl SocialPremium {
+    #[payable]
+    pub fn purchase_seats(&mut self, name: SubscriptionName, seats: u32) -> Option<Promise> {
+        let org_id = env::predecessor_account_id();
+
+        assert!(seats > 0, "ERR_ZERO_SEATS");
+        assert!(seats <= MAX_SEATS_PER_POOL, "ERR_TOO_MANY_SEATS");
+
+        self.assert_not_shared(&name);
+        let subscription = self.internal_get_subscription(&name);
+
+        let now: u128 = env::block_timestamp_ms().into();
+
+        // the storage of a new pool is paid from the attached deposit
+        let initial_storage = env::storage_usage();
+        let key = (org_id, name.clone());
+        if self.seat_pools.get(&key).is_none() {
+            self.seat_pools.insert(
+                &key,
+                &SeatPool {
+                    seats,
+                    expires_at: now,
+                    members: HashMap::new(),
+                },
+            );
+        }
+        let storage_deposit = get_storage_deposit(initial_storage);
+
+        assert!(
+            env::attached_deposit() >= MIN_DEPOSIT + storage_deposit,
+            "Deposit {} required",
+            MIN_DEPOSIT + storage_deposit
+        );
+        let deposit = env::attached_deposit() - storage_deposit;
+
+        let mut pool = self.seat_pools.get(&key).expect("ERR_NO_SEAT_POOL");
+
+        if pool.expires_at > now {
+            // active pools can only be extended
+            assert_eq!(pool.seats, seats, "ERR_SEATS_MISMATCH");
+        } else {
+            assert!(pool.members.len() <= seats as usize, "ERR_TOO_MANY_MEMBERS");
+            pool.seats = seats;
+            pool.expires_at = now;
+        }
+
+        // every seat is priced as a separate subscription
+        let seat_amount = deposit / Balance::from(seats);
+        pool.expires_at += self.get_subscription_purchased_period_ms(&subscription, seat_amount);
+
+        log!(
+            "Seats purchased by {}: {} until {}",
+            key.0,
+            seats,
+            pool.expires_at
+        );
+
+        self.deposits += deposit;
+        self.operations += 1;
+        self.internal_post_ledger_entry(
+            LedgerAccount::Available,
+            LedgerAccount::GrossRevenue,
+            deposit,
+        );
+
+        let members: Vec<SubscriptionData> = pool
+            .members
+            .iter()
+            .map(|(member_id, paid_until)| SubscriptionData {
+                receiver_id: member_id.clone(),
+                timestamp: std::cmp::max(*paid_until, pool.expires_at),
+            })
+            .collect();
+
+        // the SocialDB deposit is only spent if there are members to update
+        let net_revenue = if members.is_empty() {
+            deposit
+        } else {
+            deposit.saturating_sub(DEPOSIT_FOR_SOCIAL_SET)
+        };
+        // seat purchases can't be refunded, so the accrued shares are not kept
+        self.internal_accrue_revenue_shares(net_revenue);
+        self.internal_add_purchase_stats(&name, &key.0, deposit, 0);
+
+        self.seat_pools.insert(&key, &pool);
+
+        if members.is_empty() {
+            None
+        } else {
+            Some(self.internal_set_subscription_holder(name, members))
+        }
+    }
+
+    #[payable]
+    pub fn assign_seat(&mut self, name: SubscriptionName, member_id: AccountId) -> Promise {
+        let org_id = env::predecessor_account_id();
+
+        let mut pool = self.internal_get_active_seat_pool(&org_id, &name);
+        assert!(
+            pool.members.len() < pool.seats as usize,
+            "ERR_NO_FREE_SEATS"
+        );
+        self.assert_not_seated(&name, &member_id);
+        self.internal_vacate_expired_seat(&name, &member_id);
+
+        self.lock_account(&member_id);
+
+        // the seat is reserved until the personal timestamp of the member is known
+        let initial_storage = env::storage_usage();
+        pool.members.insert(member_id.clone(), 0);
+        self.seat_pools
+            .insert(&(org_id.clone(), name.clone()), &pool);
+        self.seat_members
+            .insert(&(member_id.clone(), name.clone()), &org_id);
+        charge_storage_deposit(initial_storage);
+
+        let keys: Vec<String> = vec![format!(
+            "{}/badge/{}/accounts/{}",
+            SOCIAL_PREMIUM_ACCOUNT_ID, name, member_id
+        )];
+
+        ext_social::ext(AccountId::new_unchecked(SOCIAL_DB_ACCOUNT_ID.to_string()))
+            .with_static_gas(GAS_FOR_SOCIAL_GET)
+            .get(keys, None)
+            .then(
+                ext_self::ext(env::current_account_id())
+                    .with_static_gas(GAS_FOR_AFTER_SOCIAL_GET)
+                    .assign_seat_after_social_get(org_id, name, member_id.clone()),
+            )
+            .then(
+                ext_self::ext(env::current_account_id())
+                    .with_static_gas(GAS_FOR_UNLOCK)
+                    .unlock_accounts(vec![member_id]),
+            )
+    }
+
+    #[private]
+    pub fn assign_seat_after_social_get(
+        &mut self,
+        #[callback_result] value: Result<Value, PromiseError>,
+        org_id: AccountId,
+        subscription_name: SubscriptionName,
+        member_id: AccountId,
+    ) {
+        let key = (org_id.clone(), subscription_name.clone());
+        let mut pool = self.seat_pools.get(&key).expect("ERR_NO_SEAT_POOL");
+
+        if let Ok(value) = value {
+            let accounts = get_accounts_data(&value, &subscription_name);
+            let paid_until = get_paid_until(accounts, &member_id).unwrap_or(0);
+
+            pool.members.insert(member_id.clone(), paid_until);
+            let timestamp = std::cmp::max(paid_until, pool.expires_at);
+
+            self.seat_pools.insert(&key, &pool);
+
+            self.internal_set_subscription_holder(
+                subscription_name,
+                vec![SubscriptionData {
+                    receiver_id: member_id,
+                    timestamp,
+                }],
+            )
+            .as_return();
+        } else {
+            // the reserved seat is released and its storage is returned to the organization
+            let initial_storage = env::storage_usage();
+            pool.members.remove(&member_id);
+            self.seat_pools.insert(&key, &pool);
+            self.seat_members.remove(&(member_id, subscription_name));
+            refund_released_storage(initial_storage, org_id);
+        }
+    }
+
+    pub fn unassign_seat(&mut self, name: SubscriptionName, member_id: AccountId) -> Promise {
+        let org_id = env::predecessor_account_id();
+
+        let initial_storage = env::storage_usage();
+        let key = (org_id.clone(), name.clone());
+        let mut pool = self.seat_pools.get(&key).expect("ERR_NO_SEAT_POOL");
+        let paid_until = pool.members.remove(&member_id).expect("ERR_NOT_A_MEMBER");
+
+        self.lock_account(&member_id);
+
+        self.seat_pools.insert(&key, &pool);
+        self.seat_members.remove(&(member_id.clone(), name.clone()));
+        refund_released_storage(initial_storage, org_id.clone());
+
+        log!("Seat of {} released by {}", member_id, org_id);
+
+        // the member keeps only the time purchased personally
+        let now: u128 = env::block_timestamp_ms().into();
+
+        self.internal_set_subscription_holder(
+            name,
+            vec![SubscriptionData {
+                receiver_id: member_id.clone(),
+                timestamp: std::cmp::max(paid_until, now),
+            }],
+        )
+        .then(
+            ext_self::ext(env::current_account_id())
+                .with_static_gas(GAS_FOR_UNLOCK)
+                .unlock_accounts(vec![member_id]),
+        )
+    }
+
+    pub fn get_seat_pool(
+        &self,
+        org_id: AccountId,
+        name: SubscriptionName,
+    ) -> Option<SeatPoolOutput> {
+        self.seat_pools.get(&(org_id, name)).map(|pool| pool.into())
+    }
+
+    pub fn get_seat_org(&self, account_id: AccountId, name: SubscriptionName) -> Option<AccountId> {
+        self.seat_members.get(&(account_id, name))
+    }
+}
+
+impl SocialPremium {
+    fn internal_get_active_seat_pool(
+        &self,
+        org_id: &AccountId,
+        name: &SubscriptionName,
+    ) -> SeatPool {
+        let pool = self
+            .seat_pools
+            .get(&(org_id.clone(), name.clone()))
+            .expect("ERR_NO_SEAT_POOL");
+
+        let now: u128 = env::block_timestamp_ms().into();
+        assert!(pool.expires_at > now, "ERR_SEAT_POOL_EXPIRED");
+
+        pool
+    }
+
+    // seats of expired pools don't count, see internal_vacate_expired_seat
+    pub(crate) fn assert_not_seated(&self, name: &SubscriptionName, account_id: &AccountId) {
+        if let Some(org_id) = self.seat_members.get(&(account_id.clone(), name.clone())) {
+            let pool = self
+                .seat_pools
+                .get(&(org_id, name.clone()))
+                .expect("ERR_NO_SEAT_POOL");
+
+            let now: u128 = env::block_timestamp_ms().into();
+            assert!(pool.expires_at <= now, "ERR_ACCOUNT_HAS_SEAT");
+        }
+    }
+
+    // seats of expired pools are vacated, their members keep only the personal time
+    pub(crate) fn internal_vacate_expired_seat(
+        &mut self,
+        name: &SubscriptionName,
+        account_id: &AccountId,
+    ) {
+        let key = (account_id.clone(), name.clone());
+        if let Some(org_id) = self.seat_members.get(&key) {
+            let pool_key = (org_id.clone(), name.clone());
+            let mut pool = self.seat_pools.get(&pool_key).expect("ERR_NO_SEAT_POOL");
+
+            let now: u128 = env::block_timestamp_ms().into();
+            if pool.expires_at > now {
+                return;
+            }
+
+            let initial_storage = env::storage_usage();
+            pool.members.remove(account_id);
+            self.seat_pools.insert(&pool_key, &pool);
+            self.seat_members.remove(&key);
+            refund_released_storage(initial_storage, org_id);
+        }
+    }
+
+    // stores the personal subscription timestamp of a seat member and returns the timestamp to
+    // write to the badge, or returns the timestamp as is for accounts without a seat
+    pub(crate) fn internal_update_seat_member(
+        &mut self,
+        name: &SubscriptionName,
+        member_id: &AccountId,
+        timestamp: u128,
+    ) -> u128 {
+        if let Some(org_id) = self.seat_members.get(&(member_id.clone(), name.clone())) {
+            let key = (org_id, name.clone());
+            let mut pool = self.seat_pools.get(&key).expect("ERR_NO_SEAT_POOL");
+            pool.members.insert(member_id.clone(), timestamp);
+            self.seat_pools.insert(&key, &pool);
+
+            std::cmp::max(timestamp, pool.expires_at)
+        } else {
+            timestamp
+        }
+    }
+
+    // personal subscription timestamp of a seat member
+    pub(crate) fn internal_get_seat_member_paid_until(
+        &self,
+        name: &SubscriptionName,
+        member_id: &AccountId,
+    ) -> Option<u128> {
+        self.seat_members
+            .get(&(member_id.clone(), name.clone()))
+            .map(|org_id| {
+                self.seat_pools
+                    .get(&(org_id, name.clone()))
+                    .expect("ERR_NO_SEAT_POOL")
+                    .members
+                    .get(member_id)
+                    .cloned()
+                    .expect("ERR_NOT_A_MEMBER")
+            })
+    }
+}
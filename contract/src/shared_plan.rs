@@ -0,0 +1,363 @@
+use crate::*;
+
+#[derive(BorshSerialize, BorshDeserialize)]
+pub struct SharedPlan {
+    pub expires_at: u128,
+    pub members: Vec<AccountId>,
+    pub invites: Vec<AccountId>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct SharedPlanOutput {
+    pub expires_at: U128,
+    pub members: Vec<AccountId>,
+    pub invites: Vec<AccountId>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct SharedPlanMembership {
+    pub payer_id: AccountId,
+    pub name: SubscriptionName,
+}
+
+impl From<SharedPlan> for SharedPlanOutput {
+    fn from(plan: SharedPlan) -> Self {
+        SharedPlanOutput {
+            expires_at: U128::from(plan.expires_at),
+            members: plan.members,
+            invites: plan.invites,
+        }
+    }
+}
+
+#[near_bindgen]
+impl SocialPremium {
+    #[payable]
+    pub fn invite_to_shared_plan(&mut self, name: SubscriptionName, account_id: AccountId) {
+        let payer_id = env::predecessor_account_id();
+        let max_members = self.internal_get_shared_plan_max_members(&name);
+
+        let key = (payer_id.clone(), name);
+        let mut plan = self.shared_plans.get(&key).expect("ERR_NO_SHARED_PLAN");
+
+        let now: u128 = env::block_timestamp_ms().into();
+        assert!(plan.expires_at > now, "ERR_SHARED_PLAN_EXPIRED");
+
+        assert_ne!(payer_id, account_id, "ERR_SELF_INVITE_NOT_ALLOWED");
+        assert!(!plan.invites.contains(&account_id), "ERR_ALREADY_INVITED");
+        self.assert_not_on_shared_plan(&account_id);
+        assert!(
+            plan.members.len() + plan.invites.len() < max_members as usize,
+            "ERR_SHARED_PLAN_IS_FULL"
+        );
+
+        let initial_storage = env::storage_usage();
+
+        plan.invites.push(account_id);
+        self.shared_plans.insert(&key, &plan);
+
+        charge_storage_deposit(initial_storage);
+    }
+
+    pub fn revoke_shared_plan_invite(&mut self, name: SubscriptionName, account_id: AccountId) {
+        let payer_id = env::predecessor_account_id();
+        let key = (payer_id.clone(), name);
+        let mut plan = self.shared_plans.get(&key).expect("ERR_NO_SHARED_PLAN");
+
+        let initial_storage = env::storage_usage();
+
+        let invites_num = plan.invites.len();
+        plan.invites.retain(|invite_id| invite_id != &account_id);
+        assert_ne!(invites_num, plan.invites.len(), "ERR_NO_INVITE");
+
+        self.shared_plans.insert(&key, &plan);
+
+        refund_released_storage(initial_storage, payer_id);
+    }
+
+    #[payable]
+    pub fn accept_shared_plan_invite(
+        &mut self,
+        name: SubscriptionName,
+        payer_id: AccountId,
+    ) -> Promise {
+        let account_id = env::predecessor_account_id();
+
+        let key = (payer_id, name.clone());
+        let mut plan = self.shared_plans.get(&key).expect("ERR_NO_SHARED_PLAN");
+
+        let now: u128 = env::block_timestamp_ms().into();
+        assert!(plan.expires_at > now, "ERR_SHARED_PLAN_EXPIRED");
+
+        let invites_num = plan.invites.len();
+        plan.invites.retain(|invite_id| invite_id != &account_id);
+        assert_ne!(invites_num, plan.invites.len(), "ERR_NO_INVITE");
+
+        self.assert_not_on_shared_plan(&account_id);
+        self.lock_account(&account_id);
+
+        // the payer keeps paying for the plan entry, the member pays for the membership
+        let initial_storage = env::storage_usage();
+
+        plan.members.push(account_id.clone());
+        self.shared_plans.insert(&key, &plan);
+        self.shared_plan_members.insert(&account_id, &key);
+
+        charge_storage_deposit(initial_storage);
+
+        log!("{} joined shared plan of {}", account_id, key.0);
+
+        self.internal_set_subscription_holder(
+            name,
+            vec![SubscriptionData {
+                receiver_id: account_id.clone(),
+                timestamp: plan.expires_at,
+            }],
+        )
+        .then(
+            ext_self::ext(env::current_account_id())
+                .with_static_gas(GAS_FOR_UNLOCK)
+                .unlock_accounts(vec![account_id]),
+        )
+    }
+
+    pub fn leave_shared_plan(&mut self) -> Option<Promise> {
+        let account_id = env::predecessor_account_id();
+
+        let key = self
+            .shared_plan_members
+            .get(&account_id)
+            .expect("ERR_NOT_ON_SHARED_PLAN");
+        let (payer_id, name) = key.clone();
+        let mut plan = self.shared_plans.get(&key).expect("ERR_NO_SHARED_PLAN");
+
+        let now: u128 = env::block_timestamp_ms().into();
+
+        if payer_id == account_id {
+            // the payer can only close the plan after it expired
+            assert!(plan.expires_at <= now, "ERR_SHARED_PLAN_IS_ACTIVE");
+
+            for member_id in plan.members {
+                let initial_storage = env::storage_usage();
+                self.shared_plan_members.remove(&member_id);
+                refund_released_storage(initial_storage, member_id);
+            }
+
+            // the payer gets back the storage of the members and invites of the plan
+            let initial_storage = env::storage_usage();
+            self.shared_plans.insert(
+                &key,
+                &SharedPlan {
+                    expires_at: plan.expires_at,
+                    members: Vec::new(),
+                    invites: Vec::new(),
+                },
+            );
+            refund_released_storage(initial_storage, payer_id.clone());
+
+            self.shared_plan_members.remove(&payer_id);
+            self.shared_plans.remove(&key);
+
+            log!("Shared plan of {} closed", payer_id);
+
+            None
+        } else {
+            self.lock_account(&account_id);
+
+            let initial_storage = env::storage_usage();
+            plan.members.retain(|member_id| member_id != &account_id);
+            self.shared_plans.insert(&key, &plan);
+            refund_released_storage(initial_storage, payer_id.clone());
+
+            let initial_storage = env::storage_usage();
+            self.shared_plan_members.remove(&account_id);
+            refund_released_storage(initial_storage, account_id.clone());
+
+            log!("{} left shared plan of {}", account_id, payer_id);
+
+            Some(
+                self.internal_set_subscription_holder(
+                    name,
+                    vec![SubscriptionData {
+                        receiver_id: account_id.clone(),
+                        timestamp: std::cmp::min(plan.expires_at, now),
+                    }],
+                )
+                .then(
+                    ext_self::ext(env::current_account_id())
+                        .with_static_gas(GAS_FOR_UNLOCK)
+                        .unlock_accounts(vec![account_id]),
+                ),
+            )
+        }
+    }
+
+    pub fn get_shared_plan(
+        &self,
+        payer_id: AccountId,
+        name: SubscriptionName,
+    ) -> Option<SharedPlanOutput> {
+        self.shared_plans
+            .get(&(payer_id, name))
+            .map(|plan| plan.into())
+    }
+
+    pub fn get_shared_plan_membership(
+        &self,
+        account_id: AccountId,
+    ) -> Option<SharedPlanMembership> {
+        self.shared_plan_members
+            .get(&account_id)
+            .map(|(payer_id, name)| SharedPlanMembership { payer_id, name })
+    }
+}
+
+impl SocialPremium {
+    fn internal_get_shared_plan_max_members(&self, name: &SubscriptionName) -> u32 {
+        match self.internal_get_subscription(name).kind {
+            SubscriptionKind::Shared { max_members } => max_members,
+            SubscriptionKind::Individual => panic!("ERR_NOT_SHARED_SUBSCRIPTION"),
+        }
+    }
+
+    fn assert_not_on_shared_plan(&self, account_id: &AccountId) {
+        assert!(
+            self.shared_plan_members.get(account_id).is_none(),
+            "ERR_ACCOUNT_ON_SHARED_PLAN"
+        );
+    }
+
+    // only the payer of the plan or accounts without a shared plan can purchase it
+    pub(crate) fn assert_can_purchase(&self, name: &SubscriptionName, account_id: &AccountId) {
+        if let SubscriptionKind::Shared { .. } = self.internal_get_subscription(name).kind {
+            if let Some(key) = self.shared_plan_members.get(account_id) {
+                assert!(
+                    key == (account_id.clone(), name.clone()),
+                    "ERR_ACCOUNT_ON_SHARED_PLAN"
+                );
+            }
+        }
+    }
+
+    pub(crate) fn assert_not_shared(&self, name: &SubscriptionName) {
+        assert!(
+            self.internal_get_subscription(name).kind == SubscriptionKind::Individual,
+            "ERR_SHARED_SUBSCRIPTION"
+        );
+    }
+
+    // updates the plan of the payer and returns the members sharing the new timestamp
+    pub(crate) fn internal_update_shared_plan(
+        &mut self,
+        name: &SubscriptionName,
+        payer_id: &AccountId,
+        timestamp: u128,
+    ) -> Vec<SubscriptionData> {
+        self.assert_can_purchase(name, payer_id);
+
+        let key = (payer_id.clone(), name.clone());
+        let mut plan = self.shared_plans.get(&key).unwrap_or(SharedPlan {
+            expires_at: timestamp,
+            members: Vec::new(),
+            invites: Vec::new(),
+        });
+        plan.expires_at = timestamp;
+
+        self.shared_plans.insert(&key, &plan);
+        self.shared_plan_members.insert(payer_id, &key);
+
+        plan.members
+            .into_iter()
+            .map(|member_id| SubscriptionData {
+                receiver_id: member_id,
+                timestamp,
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::*;
+    use near_sdk::mock::VmAction;
+    use near_sdk::test_utils::get_created_receipts;
+
+    const STORAGE_DEPOSIT: Balance = ONE_NEAR / 100;
+
+    fn setup_shared_plan() -> SocialPremium {
+        let mut contract = setup_contract(Some(3));
+        purchase(&mut contract, alice(), ONE_NEAR, None, NOW_MS);
+        contract
+    }
+
+    // sum of the transfers to the account created by the last call
+    fn get_transferred(account_id: &AccountId) -> Balance {
+        get_created_receipts()
+            .into_iter()
+            .filter(|receipt| &receipt.receiver_id == account_id)
+            .flat_map(|receipt| receipt.actions)
+            .map(|action| match action {
+                VmAction::Transfer { deposit } => deposit,
+                _ => 0,
+            })
+            .sum()
+    }
+
+    #[test]
+    fn test_shared_plan_storage() {
+        let mut contract = setup_shared_plan();
+
+        set_context(alice(), NOW_MS, STORAGE_DEPOSIT);
+        contract.invite_to_shared_plan(NAME.to_string(), bob());
+        let invite_storage = STORAGE_DEPOSIT - get_transferred(&alice());
+        assert!(invite_storage > 0);
+
+        set_context(bob(), NOW_MS, STORAGE_DEPOSIT);
+        contract.accept_shared_plan_invite(NAME.to_string(), alice());
+        let membership_storage = STORAGE_DEPOSIT - get_transferred(&bob());
+        assert!(membership_storage > 0);
+        contract.unlock_accounts(vec![bob()]);
+
+        set_context(bob(), NOW_MS, 0);
+        contract.leave_shared_plan();
+        assert_eq!(get_transferred(&alice()), invite_storage);
+        assert_eq!(get_transferred(&bob()), membership_storage);
+    }
+
+    #[test]
+    fn test_revoke_shared_plan_invite_refunds_storage() {
+        let mut contract = setup_shared_plan();
+
+        set_context(alice(), NOW_MS, STORAGE_DEPOSIT);
+        contract.invite_to_shared_plan(NAME.to_string(), bob());
+        let invite_storage = STORAGE_DEPOSIT - get_transferred(&alice());
+
+        set_context(alice(), NOW_MS, 0);
+        contract.revoke_shared_plan_invite(NAME.to_string(), bob());
+        assert_eq!(get_transferred(&alice()), invite_storage);
+    }
+
+    #[test]
+    #[should_panic(expected = "required to cover storage")]
+    fn test_invite_without_storage_deposit() {
+        let mut contract = setup_shared_plan();
+
+        set_context(alice(), NOW_MS, 0);
+        contract.invite_to_shared_plan(NAME.to_string(), bob());
+    }
+
+    #[test]
+    #[should_panic(expected = "required to cover storage")]
+    fn test_accept_without_storage_deposit() {
+        let mut contract = setup_shared_plan();
+
+        set_context(alice(), NOW_MS, STORAGE_DEPOSIT);
+        contract.invite_to_shared_plan(NAME.to_string(), bob());
+
+        set_context(bob(), NOW_MS, 0);
+        contract.accept_shared_plan_invite(NAME.to_string(), alice());
+    }
+}
@@ -4,10 +4,14 @@ use near_sdk::serde_json::{Map, Value};
 
 pub const GAS_FOR_SOCIAL_GET: Gas = Gas(Gas::ONE_TERA.0 * 10);
 pub const GAS_FOR_SOCIAL_SET: Gas = Gas(Gas::ONE_TERA.0 * 40);
-pub const GAS_FOR_AFTER_SOCIAL_GET: Gas = Gas(Gas::ONE_TERA.0 * 85);
+pub const GAS_FOR_AFTER_SOCIAL_GET: Gas = Gas(Gas::ONE_TERA.0 * 100);
+pub const GAS_FOR_AFTER_SOCIAL_GET_BATCH: Gas = Gas(Gas::ONE_TERA.0 * 150);
 pub const GAS_FOR_UNLOCK: Gas = Gas(Gas::ONE_TERA.0 * 10);
+pub const GAS_FOR_SOCIAL_STORAGE_BALANCE: Gas = Gas(Gas::ONE_TERA.0 * 5);
+pub const GAS_FOR_AFTER_SOCIAL_SET: Gas = Gas(Gas::ONE_TERA.0 * 10);
 pub const DEPOSIT_FOR_SOCIAL_SET: Balance = 50_000_000_000_000_000_000_000;
 pub const MIN_DEPOSIT: Balance = 1_000_000_000_000_000_000_000_000;
+pub const MAX_PURCHASE_BATCH_SIZE: usize = 20;
 
 #[derive(Serialize, Deserialize, Default)]
 #[serde(crate = "near_sdk::serde")]
@@ -23,10 +27,18 @@ pub struct SetOptions {
     pub refund_unused_deposit: bool,
 }
 
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct StorageBalance {
+    pub total: U128,
+    pub available: U128,
+}
+
 #[ext_contract(ext_social)]
 pub trait ExtSocial {
     fn get(self, keys: Vec<String>, options: Option<GetOptions>) -> Value;
     fn set(&mut self, data: Value, options: SetOptions);
+    fn storage_balance_of(&self, account_id: AccountId) -> Option<StorageBalance>;
 }
 
 #[ext_contract(ext_self)]
@@ -38,6 +50,15 @@ pub trait ExtSocialPremium {
         subscription_name: String,
         amount: U128,
         referral_account_id: Option<ReferralAccountId>,
+        payer_id: AccountId,
+    );
+
+    fn purchase_batch_after_social_get(
+        &mut self,
+        #[callback_result] value: Result<Value, PromiseError>,
+        subscription_name: String,
+        entries: Vec<PurchaseEntry>,
+        payer_id: AccountId,
     );
 
     fn transfer_after_social_get(
@@ -46,6 +67,66 @@ pub trait ExtSocialPremium {
         sender_id: AccountId,
         receiver_id: AccountId,
         subscription_name: String,
+        duration_ms: Option<U128>,
+        spender_id: Option<AccountId>,
+    );
+
+    fn assign_seat_after_social_get(
+        &mut self,
+        #[callback_result] value: Result<Value, PromiseError>,
+        org_id: AccountId,
+        subscription_name: String,
+        member_id: AccountId,
+    );
+
+    fn start_trial_after_social_get(
+        &mut self,
+        #[callback_result] value: Result<Value, PromiseError>,
+        account_id: AccountId,
+        subscription_name: String,
+        deposit: U128,
+    );
+
+    fn refund_after_social_get(
+        &mut self,
+        #[callback_result] value: Result<Value, PromiseError>,
+        account_id: AccountId,
+        subscription_name: String,
+        amount: Option<U128>,
+    );
+
+    fn on_referral_rewards_claimed(&mut self, account_id: AccountId, amount: U128);
+
+    fn on_revenue_share_distributed(&mut self, account_id: AccountId, amount: U128);
+
+    fn backfill_holders_after_social_get(
+        &mut self,
+        #[callback_result] value: Result<Value, PromiseError>,
+        subscription_name: String,
+        account_ids: Vec<AccountId>,
+    );
+
+    fn on_refund_transferred(
+        &mut self,
+        account_id: AccountId,
+        subscription_name: String,
+        operation_id: u64,
+        refund: U128,
+        refunded_ms: U128,
+        expires_at: U128,
+    );
+
+    fn on_expired_badges_removed(
+        &mut self,
+        subscription_name: String,
+        holders: Vec<HolderOutput>,
+        keeper_id: AccountId,
+    ) -> u64;
+
+    fn on_social_storage_balance(
+        &mut self,
+        #[callback_result] value: Result<Option<StorageBalance>, PromiseError>,
+        deposit: U128,
     );
 
     fn unlock_accounts(&mut self, accounts: Vec<AccountId>);
@@ -68,112 +149,87 @@ impl SocialPremium {
         subscription_name: SubscriptionName,
         amount: U128,
         referral_account_id: Option<ReferralAccountId>,
+        payer_id: AccountId,
     ) {
-        if let Ok(mut value) = value {
-            let keys = value.as_object_mut().expect("Data is not a JSON object");
-
-            let mut referral_is_premium = false;
+        if let Ok(value) = value {
+            let accounts = get_accounts_data(&value, &subscription_name);
 
             let now: u128 = env::block_timestamp_ms().into();
 
-            let paid_until: u128 = if keys.is_empty() {
-                now
-            } else {
-                let badge = value
-                    .get(SOCIAL_PREMIUM_ACCOUNT_ID.to_string())
-                    .expect("ERR_NO_DATA");
-                let subscriptions = badge.get("badge".to_string()).expect("ERR_NO_DATA");
-                let subscription = subscriptions
-                    .get(subscription_name.to_string())
-                    .expect("ERR_NO_DATA");
-                let accounts = subscription
-                    .get("accounts".to_string())
-                    .expect("ERR_NO_DATA");
-                let paid_until = accounts
-                    .get(receiver_id.to_string())
-                    .unwrap_or(&Value::from(now.to_string()))
-                    .as_str()
-                    .unwrap()
-                    .to_string();
-
-                if let Some(referral_id) = referral_account_id.clone() {
-                    let referral_paid_until =
-                        if let Some(referral_paid_until_value) = accounts.get(referral_id.to_string()) {
-                            referral_paid_until_value
-                                .as_str()
-                                .unwrap()
-                                .parse::<u128>()
-                                .unwrap()
-                        } else {
-                            0
-                        };
-
-                    referral_is_premium = referral_paid_until > now;
-                }
+            let paid_until = get_paid_until(accounts, &receiver_id).unwrap_or(now);
+
+            let referral = referral_account_id.map(|referral_id| {
+                let referral_is_premium = get_paid_until(accounts, &referral_id).unwrap_or(0) > now;
+                (referral_id, referral_is_premium)
+            });
+
+            let mut social_set_cost = DEPOSIT_FOR_SOCIAL_SET;
+            let subscriptions = self.internal_purchase(
+                &subscription_name,
+                &receiver_id,
+                &payer_id,
+                amount.0,
+                paid_until,
+                referral,
+                &mut social_set_cost,
+            );
 
-                paid_until.parse::<u128>().unwrap_or(now)
-            };
+            self.internal_set_subscription_holder(subscription_name, subscriptions)
+                .as_return();
+        }
+    }
 
-            // store affiliate reward
-            if let Some(user_referral_id) = referral_account_id {
-                self.referrals.insert(&receiver_id, &user_referral_id);
+    #[private]
+    pub fn purchase_batch_after_social_get(
+        &mut self,
+        #[callback_result] value: Result<Value, PromiseError>,
+        subscription_name: SubscriptionName,
+        entries: Vec<PurchaseEntry>,
+        payer_id: AccountId,
+    ) {
+        if let Ok(value) = value {
+            let accounts = get_accounts_data(&value, &subscription_name);
 
-                let prev_referral_reward = self
-                    .referral_rewards
-                    .get(&user_referral_id)
-                    .unwrap_or_default();
-                let referral_reward = if referral_is_premium {
-                    self.premium_referral_fee.multiply(amount.0)
-                } else {
-                    self.referral_fee.multiply(amount.0)
-                };
-                self.referral_rewards
-                    .insert(&user_referral_id, &(prev_referral_reward + referral_reward));
-                self.total_referral_rewards += referral_reward;
-
-                log!(
-                    "{}Referral reward for {}: {} yNEAR",
-                    if referral_is_premium { "Premium " } else { "" },
-                    user_referral_id,
-                    referral_reward.to_string()
-                );
+            let now: u128 = env::block_timestamp_ms().into();
 
-                Promise::new(user_referral_id).transfer(referral_reward);
+            // all entries are written with a single set
+            let mut social_set_cost = DEPOSIT_FOR_SOCIAL_SET;
+
+            let mut subscriptions: Vec<SubscriptionData> = Vec::new();
+            for entry in entries {
+                let paid_until = get_paid_until(accounts, &entry.receiver_id).unwrap_or(now);
+
+                let referral = entry.referral_account_id.map(|referral_id| {
+                    let referral_is_premium =
+                        get_paid_until(accounts, &referral_id).unwrap_or(0) > now;
+                    (referral_id, referral_is_premium)
+                });
+
+                subscriptions.extend(self.internal_purchase(
+                    &subscription_name,
+                    &entry.receiver_id,
+                    &payer_id,
+                    entry.amount.0,
+                    paid_until,
+                    referral,
+                    &mut social_set_cost,
+                ));
             }
 
-            let subscription = self.internal_get_subscription(&subscription_name);
-
-            let previously_purchased_ms = if paid_until > now {
-                paid_until - now
-            } else {
-                0
-            };
-
-            let purchased_period_ms =
-                self.get_subscription_purchased_period_ms(&subscription, amount.0);
-
-            let subscription_timestamp = now + purchased_period_ms + previously_purchased_ms;
-
-            self.deposits += amount.0;
-            self.operations += 1;
-
-            self.internal_set_subscription_holder(
-                subscription_name,
-                vec![SubscriptionData {
-                    receiver_id,
-                    timestamp: subscription_timestamp,
-                }],
-            )
-            .as_return();
+            self.internal_set_subscription_holder(subscription_name, subscriptions)
+                .as_return();
         }
     }
 
+    #[private]
     pub fn transfer_after_social_get(
         &mut self,
         #[callback_result] value: Result<Value, PromiseError>,
         sender_id: AccountId,
         receiver_id: AccountId,
         subscription_name: SubscriptionName,
+        duration_ms: Option<U128>,
+        spender_id: Option<AccountId>,
     ) {
         if let Ok(mut value) = value {
             let keys = value.as_object_mut().expect("Data is not a JSON object");
@@ -219,16 +275,60 @@ impl SocialPremium {
 
                 let sender_previous_purchased_ms = sender_paid_until - now;
 
-                let receiver_timestamp = sender_previous_purchased_ms + receiver_paid_until;
+                // partial transfers move only the requested duration
+                let transferred_ms = if let Some(duration_ms) = duration_ms {
+                    assert!(duration_ms.0 > 0, "ERR_ZERO_DURATION");
+                    assert!(
+                        duration_ms.0 <= sender_previous_purchased_ms,
+                        "ERR_NOT_ENOUGH_SUBSCRIPTION_TIME"
+                    );
+                    duration_ms.0
+                } else {
+                    sender_previous_purchased_ms
+                };
+
+                if let Some(spender_id) = spender_id {
+                    self.internal_use_approval(
+                        &sender_id,
+                        &subscription_name,
+                        &spender_id,
+                        transferred_ms,
+                    );
+                }
+
+                let sender_timestamp = sender_paid_until - transferred_ms;
+                let receiver_timestamp = transferred_ms + receiver_paid_until;
 
                 self.operations += 1;
+                self.internal_add_transfer_stats(&subscription_name);
+
+                self.internal_add_history_receipt(
+                    &sender_id,
+                    HistoryKind::TransferOut,
+                    &subscription_name,
+                    0,
+                    transferred_ms,
+                    sender_timestamp,
+                    None,
+                    Some(receiver_id.clone()),
+                );
+                self.internal_add_history_receipt(
+                    &receiver_id,
+                    HistoryKind::TransferIn,
+                    &subscription_name,
+                    0,
+                    transferred_ms,
+                    receiver_timestamp,
+                    None,
+                    Some(sender_id.clone()),
+                );
 
                 self.internal_set_subscription_holder(
                     subscription_name,
                     vec![
                         SubscriptionData {
                             receiver_id: sender_id,
-                            timestamp: now,
+                            timestamp: sender_timestamp,
                         },
                         SubscriptionData {
                             receiver_id,
@@ -242,17 +342,198 @@ impl SocialPremium {
     }
 }
 
-struct SubscriptionData {
-    receiver_id: AccountId,
-    timestamp: u128,
+pub(crate) struct SubscriptionData {
+    pub receiver_id: AccountId,
+    pub timestamp: u128,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct PurchaseEntry {
+    pub receiver_id: AccountId,
+    pub amount: U128,
+    pub referral_account_id: Option<ReferralAccountId>,
 }
 
 impl SocialPremium {
-    fn internal_set_subscription_holder(
+    // stores the purchase and the affiliate reward, returns the new subscription timestamps
+    // of the receiver and the members sharing the subscription
+    // social_set_cost is the part of the SocialDB deposit not yet covered by the revenue
+    #[allow(clippy::too_many_arguments)]
+    fn internal_purchase(
+        &mut self,
+        subscription_name: &SubscriptionName,
+        receiver_id: &AccountId,
+        payer_id: &AccountId,
+        amount: Balance,
+        paid_until: u128,
+        referral: Option<(ReferralAccountId, bool)>,
+        social_set_cost: &mut Balance,
+    ) -> Vec<SubscriptionData> {
+        let now: u128 = env::block_timestamp_ms().into();
+
+        self.deposits += amount;
+        self.operations += 1;
+        self.internal_post_ledger_entry(
+            LedgerAccount::Available,
+            LedgerAccount::GrossRevenue,
+            amount,
+        );
+
+        let referral_account_id = referral
+            .as_ref()
+            .map(|(referral_account_id, _)| referral_account_id.clone());
+
+        // store affiliate reward
+        let referral_reward = if let Some((user_referral_id, referral_is_premium)) = referral {
+            self.internal_add_referral_reward(
+                receiver_id,
+                user_referral_id,
+                amount,
+                referral_is_premium,
+                self.operations,
+            )
+        } else {
+            0
+        };
+
+        let revenue = amount.saturating_sub(referral_reward);
+        let covered_set_cost = std::cmp::min(revenue, *social_set_cost);
+        *social_set_cost -= covered_set_cost;
+
+        let revenue_shares = self.internal_accrue_revenue_shares(revenue - covered_set_cost);
+        self.internal_add_purchase_stats(subscription_name, payer_id, amount, referral_reward);
+
+        let subscription = self.internal_get_subscription(subscription_name);
+
+        // seat members extend their own time, not the time of the organization
+        let paid_until = self
+            .internal_get_seat_member_paid_until(subscription_name, receiver_id)
+            .unwrap_or(paid_until);
+
+        // holders in the grace period renew from their expiry as if the subscription never lapsed
+        let renew_from = if paid_until + subscription.grace_period_ms > now
+            && !self
+                .revoked_holders
+                .contains(&(subscription_name.clone(), receiver_id.clone()))
+        {
+            paid_until
+        } else {
+            now
+        };
+
+        let purchased_period_ms = self.get_subscription_purchased_period_ms(&subscription, amount);
+
+        let subscription_timestamp = renew_from + purchased_period_ms;
+
+        self.paid_holders
+            .insert(&(subscription_name.clone(), receiver_id.clone()));
+
+        self.internal_record_refundable_purchase(
+            subscription_name,
+            receiver_id,
+            payer_id,
+            amount,
+            purchased_period_ms,
+            subscription_timestamp,
+            revenue_shares,
+        );
+
+        let receiver_timestamp = self.internal_update_seat_member(
+            subscription_name,
+            receiver_id,
+            subscription_timestamp,
+        );
+
+        if receiver_id == payer_id {
+            self.internal_add_history_receipt(
+                receiver_id,
+                HistoryKind::Purchase,
+                subscription_name,
+                amount,
+                purchased_period_ms,
+                receiver_timestamp,
+                referral_account_id,
+                None,
+            );
+        } else {
+            self.internal_add_history_receipt(
+                payer_id,
+                HistoryKind::Purchase,
+                subscription_name,
+                amount,
+                purchased_period_ms,
+                receiver_timestamp,
+                referral_account_id.clone(),
+                Some(receiver_id.clone()),
+            );
+            self.internal_add_history_receipt(
+                receiver_id,
+                HistoryKind::Grant,
+                subscription_name,
+                amount,
+                purchased_period_ms,
+                receiver_timestamp,
+                referral_account_id,
+                Some(payer_id.clone()),
+            );
+        }
+
+        let mut subscriptions = vec![SubscriptionData {
+            receiver_id: receiver_id.clone(),
+            timestamp: receiver_timestamp,
+        }];
+
+        if let SubscriptionKind::Shared { .. } = subscription.kind {
+            subscriptions.extend(self.internal_update_shared_plan(
+                subscription_name,
+                receiver_id,
+                subscription_timestamp,
+            ));
+        }
+
+        subscriptions
+    }
+
+    // holder_expiries mirrors the timestamps written to the badges
+    pub(crate) fn internal_index_holders(
+        &mut self,
+        subscription_name: &SubscriptionName,
+        subscriptions: &[SubscriptionData],
+    ) {
+        let prev_expiries: Vec<Option<u128>> = subscriptions
+            .iter()
+            .map(|subscription| {
+                self.holder_expiries.insert(
+                    &(subscription_name.clone(), subscription.receiver_id.clone()),
+                    &subscription.timestamp,
+                )
+            })
+            .collect();
+
+        self.internal_update_expiry_index(subscription_name, subscriptions, &prev_expiries);
+        self.internal_update_holder_stats(subscription_name, subscriptions, &prev_expiries);
+        self.internal_update_holders(subscription_name, subscriptions);
+        self.internal_update_revoked_holders(subscription_name, subscriptions);
+    }
+
+    pub(crate) fn internal_set_subscription_holder(
+        &mut self,
+        subscription_name: SubscriptionName,
+        subscriptions: Vec<SubscriptionData>,
+    ) -> Promise {
+        self.internal_reserve_social_set_deposit();
+        self.internal_write_subscription_holder(subscription_name, subscriptions)
+    }
+
+    // writes the badges with the SocialDB deposit reserved by the caller
+    pub(crate) fn internal_write_subscription_holder(
         &mut self,
         subscription_name: SubscriptionName,
         subscriptions: Vec<SubscriptionData>,
     ) -> Promise {
+        self.internal_index_holders(&subscription_name, &subscriptions);
+
         let mut data: Map<String, Value> = Map::new();
 
         let badge_data = get_badge_data(&subscription_name, subscriptions);
@@ -261,15 +542,7 @@ impl SocialPremium {
             Value::Object(badge_data),
         );
 
-        ext_social::ext(AccountId::new_unchecked(SOCIAL_DB_ACCOUNT_ID.to_string()))
-            .with_static_gas(GAS_FOR_SOCIAL_SET)
-            .with_attached_deposit(DEPOSIT_FOR_SOCIAL_SET)
-            .set(
-                Value::Object(data),
-                SetOptions {
-                    refund_unused_deposit: true,
-                },
-            )
+        self.internal_social_set(Value::Object(data))
     }
 
     pub fn internal_set_subscription(&mut self, subscription_name: SubscriptionName) {
@@ -312,18 +585,69 @@ impl SocialPremium {
             Value::Object(badge_data),
         );
 
+        self.internal_reserve_social_set_deposit();
+        self.internal_social_set(Value::Object(data));
+    }
+}
+
+impl SocialPremium {
+    // the deposit is reserved as storage spend until the unused part is refunded by the SocialDB
+    pub(crate) fn internal_reserve_social_set_deposit(&mut self) {
+        self.internal_post_ledger_entry(
+            LedgerAccount::StorageSpend,
+            LedgerAccount::Available,
+            DEPOSIT_FOR_SOCIAL_SET,
+        );
+    }
+
+    // sends the reserved deposit with the data
+    fn internal_social_set(&mut self, data: Value) -> Promise {
         ext_social::ext(AccountId::new_unchecked(SOCIAL_DB_ACCOUNT_ID.to_string()))
             .with_static_gas(GAS_FOR_SOCIAL_SET)
             .with_attached_deposit(DEPOSIT_FOR_SOCIAL_SET)
             .set(
-                Value::Object(data),
+                data,
                 SetOptions {
                     refund_unused_deposit: true,
                 },
-            );
+            )
+            .then(
+                ext_social::ext(AccountId::new_unchecked(SOCIAL_DB_ACCOUNT_ID.to_string()))
+                    .with_static_gas(GAS_FOR_SOCIAL_STORAGE_BALANCE)
+                    .storage_balance_of(env::current_account_id()),
+            )
+            .then(
+                ext_self::ext(env::current_account_id())
+                    .with_static_gas(GAS_FOR_AFTER_SOCIAL_SET)
+                    .on_social_storage_balance(U128::from(DEPOSIT_FOR_SOCIAL_SET)),
+            )
     }
 }
 
+// returns paid until timestamps of the subscription accounts from the SocialDB response
+pub(crate) fn get_accounts_data<'a>(
+    value: &'a Value,
+    subscription_name: &str,
+) -> Option<&'a Value> {
+    let keys = value.as_object().expect("Data is not a JSON object");
+
+    if keys.is_empty() {
+        None
+    } else {
+        let badge = value.get(SOCIAL_PREMIUM_ACCOUNT_ID).expect("ERR_NO_DATA");
+        let subscriptions = badge.get("badge").expect("ERR_NO_DATA");
+        let subscription = subscriptions.get(subscription_name).expect("ERR_NO_DATA");
+        Some(subscription.get("accounts").expect("ERR_NO_DATA"))
+    }
+}
+
+pub(crate) fn get_paid_until(accounts: Option<&Value>, account_id: &AccountId) -> Option<u128> {
+    accounts
+        .and_then(|accounts| accounts.get(account_id.as_str()))
+        .and_then(|paid_until| paid_until.as_str())
+        .and_then(|paid_until| paid_until.parse::<u128>().ok())
+}
+
 fn get_badge_data(
     subscription_name: &String,
     subscriptions: Vec<SubscriptionData>,
@@ -336,6 +660,14 @@ fn get_badge_data(
         );
     }
 
+    get_badge_accounts_data(subscription_name, receiver_data)
+}
+
+// wraps the values of the subscription accounts into the badge data
+pub(crate) fn get_badge_accounts_data(
+    subscription_name: &String,
+    receiver_data: Map<String, Value>,
+) -> Map<String, Value> {
     let mut accounts_data: Map<String, Value> = Map::new();
     accounts_data.insert("accounts".to_string(), Value::Object(receiver_data));
 
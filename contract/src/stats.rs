@@ -0,0 +1,244 @@
+use crate::*;
+
+// max number of expired buckets removed from the active holders in a single update,
+// the rest is subtracted by the stats view until the next updates remove it
+pub const MAX_EXPIRED_BUCKETS_PER_UPDATE: usize = 50;
+
+#[derive(BorshSerialize, BorshDeserialize, Default)]
+pub struct SubscriptionStats {
+    pub revenue: Balance,
+    pub purchases: u64,
+    pub transfers: u64,
+    pub unique_buyers: u64,
+    // accounts which ever held the subscription
+    pub holders: u64,
+    // holders not expired before the last holder update
+    pub active_holders: u64,
+    pub referral_spend: Balance,
+    pub refunds: Balance,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct SubscriptionStatsOutput {
+    pub revenue: U128,
+    pub purchases: u64,
+    pub transfers: u64,
+    pub unique_buyers: u64,
+    pub holders: u64,
+    pub active_holders: u64,
+    pub referral_spend: U128,
+    pub refunds: U128,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub enum RevenueInterval {
+    Daily,
+    Monthly,
+}
+
+#[near_bindgen]
+impl SocialPremium {
+    pub fn get_subscription_stats(&self, name: SubscriptionName) -> SubscriptionStatsOutput {
+        let stats = self.subscription_stats.get(&name).unwrap_or_default();
+
+        // holders expired since the last update are still counted as active in the stored stats
+        let now: u128 = env::block_timestamp_ms().into();
+        let expired_holders: u64 = self
+            .holder_expiry_counts
+            .get(&name)
+            .map(|expiry_counts| {
+                expiry_counts
+                    .iter()
+                    .take_while(|(expires_at, _)| *expires_at <= now)
+                    .map(|(_, count)| count)
+                    .sum()
+            })
+            .unwrap_or(0);
+
+        SubscriptionStatsOutput {
+            revenue: U128::from(stats.revenue),
+            purchases: stats.purchases,
+            transfers: stats.transfers,
+            unique_buyers: stats.unique_buyers,
+            holders: stats.holders,
+            active_holders: stats.active_holders - expired_holders,
+            referral_spend: U128::from(stats.referral_spend),
+            refunds: U128::from(stats.refunds),
+        }
+    }
+
+    // returns the revenue of the consecutive days or months starting from the given one
+    pub fn get_revenue_buckets(
+        &self,
+        name: SubscriptionName,
+        interval: RevenueInterval,
+        from_index: u64,
+        limit: Option<u64>,
+    ) -> Vec<(u64, U128)> {
+        let buckets = match interval {
+            RevenueInterval::Daily => &self.daily_revenue,
+            RevenueInterval::Monthly => &self.monthly_revenue,
+        };
+
+        (from_index..from_index + limit.unwrap_or(DEFAULT_PAGINATION_LIMIT))
+            .map(|index| {
+                (
+                    index,
+                    U128::from(buckets.get(&(name.clone(), index)).unwrap_or_default()),
+                )
+            })
+            .collect()
+    }
+
+    pub fn get_revenue_bucket_index(&self, interval: RevenueInterval) -> u64 {
+        let now: u128 = env::block_timestamp_ms().into();
+        match interval {
+            RevenueInterval::Daily => (now / DAY_IN_MS) as u64,
+            RevenueInterval::Monthly => (now / MONTH_IN_MS) as u64,
+        }
+    }
+}
+
+impl SocialPremium {
+    pub(crate) fn internal_add_purchase_stats(
+        &mut self,
+        subscription_name: &SubscriptionName,
+        payer_id: &AccountId,
+        amount: Balance,
+        referral_spend: Balance,
+    ) {
+        let mut stats = self
+            .subscription_stats
+            .get(subscription_name)
+            .unwrap_or_default();
+
+        stats.revenue += amount;
+        stats.purchases += 1;
+        stats.referral_spend += referral_spend;
+
+        if self
+            .subscription_buyers
+            .insert(&(subscription_name.clone(), payer_id.clone()))
+        {
+            stats.unique_buyers += 1;
+        }
+
+        self.subscription_stats.insert(subscription_name, &stats);
+
+        let now: u128 = env::block_timestamp_ms().into();
+        add_revenue(
+            &mut self.daily_revenue,
+            (subscription_name.clone(), (now / DAY_IN_MS) as u64),
+            amount,
+        );
+        add_revenue(
+            &mut self.monthly_revenue,
+            (subscription_name.clone(), (now / MONTH_IN_MS) as u64),
+            amount,
+        );
+    }
+
+    pub(crate) fn internal_add_transfer_stats(&mut self, subscription_name: &SubscriptionName) {
+        let mut stats = self
+            .subscription_stats
+            .get(subscription_name)
+            .unwrap_or_default();
+        stats.transfers += 1;
+        self.subscription_stats.insert(subscription_name, &stats);
+    }
+
+    pub(crate) fn internal_add_refund_stats(
+        &mut self,
+        subscription_name: &SubscriptionName,
+        refund: Balance,
+        referral_clawback: Balance,
+    ) {
+        let mut stats = self
+            .subscription_stats
+            .get(subscription_name)
+            .unwrap_or_default();
+        stats.refunds += refund;
+        stats.referral_spend = stats.referral_spend.saturating_sub(referral_clawback);
+        self.subscription_stats.insert(subscription_name, &stats);
+    }
+
+    // keeps the holder counters in sync with the timestamps written to the SocialDB
+    pub(crate) fn internal_update_holder_stats(
+        &mut self,
+        subscription_name: &SubscriptionName,
+        subscriptions: &[SubscriptionData],
+        prev_expiries: &[Option<u128>],
+    ) {
+        let now: u128 = env::block_timestamp_ms().into();
+
+        let mut stats = self
+            .subscription_stats
+            .get(subscription_name)
+            .unwrap_or_default();
+        let mut expiry_counts = self
+            .holder_expiry_counts
+            .get(subscription_name)
+            .unwrap_or_else(|| {
+                TreeMap::new(StorageKey::HolderExpiryCountsPerSubscription {
+                    subscription_hash: env::sha256_array(subscription_name.as_bytes()),
+                })
+            });
+
+        // expired holders leave the active counter
+        let expired: Vec<(u128, u64)> = expiry_counts
+            .iter()
+            .take(MAX_EXPIRED_BUCKETS_PER_UPDATE)
+            .take_while(|(expires_at, _)| *expires_at <= now)
+            .collect();
+        for (expires_at, count) in expired {
+            expiry_counts.remove(&expires_at);
+            stats.active_holders -= count;
+        }
+
+        for (subscription, prev_expires_at) in subscriptions.iter().zip(prev_expiries) {
+            match *prev_expires_at {
+                Some(prev_expires_at) if prev_expires_at > now => {
+                    let count = expiry_counts.get(&prev_expires_at).unwrap_or_default();
+                    if count > 1 {
+                        expiry_counts.insert(&prev_expires_at, &(count - 1));
+                    } else {
+                        expiry_counts.remove(&prev_expires_at);
+                    }
+                    stats.active_holders -= 1;
+                }
+                Some(_) => {}
+                None => {
+                    if !self
+                        .former_holders
+                        .contains(&(subscription_name.clone(), subscription.receiver_id.clone()))
+                    {
+                        stats.holders += 1;
+                    }
+                }
+            }
+
+            if subscription.timestamp > now {
+                let count = expiry_counts
+                    .get(&subscription.timestamp)
+                    .unwrap_or_default();
+                expiry_counts.insert(&subscription.timestamp, &(count + 1));
+                stats.active_holders += 1;
+            }
+        }
+
+        self.holder_expiry_counts
+            .insert(subscription_name, &expiry_counts);
+        self.subscription_stats.insert(subscription_name, &stats);
+    }
+}
+
+fn add_revenue(
+    buckets: &mut LookupMap<(SubscriptionName, u64), Balance>,
+    key: (SubscriptionName, u64),
+    amount: Balance,
+) {
+    let revenue = buckets.get(&key).unwrap_or_default();
+    buckets.insert(&key, &(revenue + amount));
+}
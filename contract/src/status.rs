@@ -0,0 +1,348 @@
+use crate::*;
+
+use near_sdk::serde_json::json;
+
+pub const DEFAULT_GAS_FOR_PREMIUM_CALLBACK: Gas = Gas(Gas::ONE_TERA.0 * 20);
+
+#[derive(Serialize, Deserialize, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub enum HolderStatus {
+    Active,
+    // expired, but still premium and able to renew without a gap
+    Grace,
+    Expired,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct HolderStatusOutput {
+    pub status: HolderStatus,
+    pub expires_at: Option<U128>,
+    pub grace_until: Option<U128>,
+}
+
+#[near_bindgen]
+impl SocialPremium {
+    pub fn set_subscription_grace_period(&mut self, name: SubscriptionName, grace_period_ms: U128) {
+        self.assert_owner();
+
+        let mut subscription = self.internal_get_subscription(&name);
+        subscription.grace_period_ms = grace_period_ms.0;
+
+        self.subscriptions
+            .insert(&name, &VSubscription::Current(subscription));
+    }
+
+    // accounts never written by this contract are reported as expired
+    pub fn get_subscription_status(
+        &self,
+        account_id: AccountId,
+        name: SubscriptionName,
+    ) -> HolderStatusOutput {
+        let subscription = self.internal_get_subscription(&name);
+
+        let key = (name, account_id);
+        let expires_at = self.holder_expiries.get(&key);
+        // the grace period starts only when the time runs out, not when it is taken away
+        let grace_until = expires_at
+            .filter(|_| !self.revoked_holders.contains(&key))
+            .map(|expires_at| expires_at + subscription.grace_period_ms);
+
+        let now: u128 = env::block_timestamp_ms().into();
+        let status = match (expires_at, grace_until) {
+            (Some(expires_at), _) if expires_at > now => HolderStatus::Active,
+            (_, Some(grace_until)) if grace_until > now => HolderStatus::Grace,
+            _ => HolderStatus::Expired,
+        };
+
+        HolderStatusOutput {
+            status,
+            expires_at: expires_at.map(U128::from),
+            grace_until: grace_until.map(U128::from),
+        }
+    }
+
+    // holders in the grace period are premium, the status is backed by the contract state,
+    // the badges in the SocialDB keep the paid expiry and are not a premium check
+    pub fn is_premium(&self, account_id: AccountId, name: SubscriptionName) -> bool {
+        self.get_subscription_status(account_id, name).status != HolderStatus::Expired
+    }
+
+    pub fn get_expiry(&self, account_id: AccountId, name: SubscriptionName) -> Option<U128> {
+        self.holder_expiries
+            .get(&(name, account_id))
+            .map(U128::from)
+    }
+
+    // calls callback_method of the caller with is_premium, account_id, name, expires_at,
+    // grace_until and msg, the callback is called for not premium accounts as well
+    pub fn check_premium_then(
+        &mut self,
+        account_id: AccountId,
+        name: SubscriptionName,
+        callback_method: String,
+        msg: Option<String>,
+        callback_gas: Option<Gas>,
+    ) -> Promise {
+        let status = self.get_subscription_status(account_id.clone(), name.clone());
+
+        let args = json!({
+            "is_premium": status.status != HolderStatus::Expired,
+            "account_id": account_id,
+            "name": name,
+            "expires_at": status.expires_at,
+            "grace_until": status.grace_until,
+            "msg": msg,
+        });
+
+        Promise::new(env::predecessor_account_id()).function_call(
+            callback_method,
+            args.to_string().into_bytes(),
+            0,
+            callback_gas.unwrap_or(DEFAULT_GAS_FOR_PREMIUM_CALLBACK),
+        )
+    }
+}
+
+impl SocialPremium {
+    // a timestamp which is not in the future ends the time before its expiry, e.g. when
+    // it is transferred away, refunded or unassigned, a later purchase lifts the mark
+    pub(crate) fn internal_update_revoked_holders(
+        &mut self,
+        subscription_name: &SubscriptionName,
+        subscriptions: &[SubscriptionData],
+    ) {
+        let now: u128 = env::block_timestamp_ms().into();
+
+        for subscription in subscriptions {
+            let key = (subscription_name.clone(), subscription.receiver_id.clone());
+            if subscription.timestamp > now {
+                self.revoked_holders.remove(&key);
+            } else {
+                self.revoked_holders.insert(&key);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::*;
+    use near_sdk::mock::VmAction;
+    use near_sdk::serde_json::Value;
+    use near_sdk::test_utils::get_created_receipts;
+    use near_sdk::PromiseResult;
+
+    const GRACE_MS: u128 = DAY_IN_MS;
+
+    fn setup_grace(max_members: Option<u32>) -> SocialPremium {
+        let mut contract = setup_contract(max_members);
+        contract.set_subscription_grace_period(NAME.to_string(), U128::from(GRACE_MS));
+        contract
+    }
+
+    fn get_status(contract: &SocialPremium, account_id: AccountId, now_ms: u128) -> HolderStatus {
+        set_context(account_id.clone(), now_ms, 0);
+        let status = contract
+            .get_subscription_status(account_id.clone(), NAME.to_string())
+            .status;
+        assert_eq!(
+            contract.is_premium(account_id, NAME.to_string()),
+            status != HolderStatus::Expired
+        );
+        status
+    }
+
+    #[test]
+    fn test_grace_after_natural_expiry() {
+        let mut contract = setup_grace(None);
+        purchase(&mut contract, alice(), ONE_NEAR, None, NOW_MS);
+
+        let expires_at = NOW_MS + MONTH_IN_MS;
+        assert!(get_status(&contract, alice(), NOW_MS) == HolderStatus::Active);
+        assert!(get_status(&contract, alice(), expires_at) == HolderStatus::Grace);
+        assert!(get_status(&contract, alice(), expires_at + GRACE_MS) == HolderStatus::Expired);
+    }
+
+    #[test]
+    fn test_no_grace_after_transfer() {
+        let mut contract = setup_grace(None);
+        purchase(&mut contract, alice(), ONE_NEAR, None, NOW_MS);
+
+        transfer(&mut contract, alice(), bob(), NOW_MS + 1_000);
+
+        assert!(get_status(&contract, alice(), NOW_MS + 1_000) == HolderStatus::Expired);
+        assert!(get_status(&contract, bob(), NOW_MS + 1_000) == HolderStatus::Active);
+
+        // the receiver keeps the grace period of the time running out naturally
+        let expires_at = NOW_MS + 1_000 + MONTH_IN_MS;
+        assert!(get_status(&contract, bob(), expires_at) == HolderStatus::Grace);
+    }
+
+    #[test]
+    fn test_purchase_after_transfer_restores_grace() {
+        let mut contract = setup_grace(None);
+        purchase(&mut contract, alice(), ONE_NEAR, None, NOW_MS);
+        transfer(&mut contract, alice(), bob(), NOW_MS + 1_000);
+
+        // the purchase starts from now, not from the transferred away expiry
+        purchase(&mut contract, alice(), ONE_NEAR, None, NOW_MS + 2_000);
+        let expires_at = NOW_MS + 2_000 + MONTH_IN_MS;
+        assert_eq!(
+            contract.get_expiry(alice(), NAME.to_string()),
+            Some(U128::from(expires_at))
+        );
+        assert!(get_status(&contract, alice(), expires_at) == HolderStatus::Grace);
+    }
+
+    #[test]
+    fn test_no_grace_after_refund() {
+        let mut contract = setup_grace(None);
+        contract.set_refund_policy(RefundPolicyInput {
+            window_ms: U128::from(DAY_IN_MS),
+            prorated: false,
+            fee: fee(0, 1),
+        });
+        purchase(&mut contract, bob(), 2 * ONE_NEAR, None, NOW_MS);
+        purchase(&mut contract, alice(), ONE_NEAR, None, NOW_MS);
+        let operation_id = contract
+            .get_refundable_purchase(alice(), NAME.to_string())
+            .unwrap()
+            .operation_id;
+
+        set_callback_context(NOW_MS, PromiseResult::Successful(vec![]));
+        contract.refund_after_social_get(
+            Ok(get_badges(&contract, &[alice()])),
+            alice(),
+            NAME.to_string(),
+            None,
+        );
+        contract.on_refund_transferred(
+            alice(),
+            NAME.to_string(),
+            operation_id,
+            U128::from(ONE_NEAR),
+            U128::from(MONTH_IN_MS),
+            U128::from(NOW_MS),
+        );
+
+        assert!(get_status(&contract, alice(), NOW_MS) == HolderStatus::Expired);
+    }
+
+    #[test]
+    fn test_no_grace_after_leaving_shared_plan() {
+        let mut contract = setup_grace(Some(3));
+        purchase(&mut contract, alice(), ONE_NEAR, None, NOW_MS);
+
+        set_context(alice(), NOW_MS, ONE_NEAR / 100);
+        contract.invite_to_shared_plan(NAME.to_string(), bob());
+        set_context(bob(), NOW_MS, ONE_NEAR / 100);
+        contract.accept_shared_plan_invite(NAME.to_string(), alice());
+        contract.unlock_accounts(vec![bob()]);
+        assert!(get_status(&contract, bob(), NOW_MS) == HolderStatus::Active);
+
+        set_context(bob(), NOW_MS + 1_000, 0);
+        contract.leave_shared_plan();
+
+        assert!(get_status(&contract, bob(), NOW_MS + 1_000) == HolderStatus::Expired);
+    }
+
+    #[test]
+    fn test_no_grace_after_unassigned_seat() {
+        let mut contract = setup_grace(None);
+
+        set_context(carol(), NOW_MS, 2 * ONE_NEAR);
+        contract.purchase_seats(NAME.to_string(), 1);
+        set_context(carol(), NOW_MS, ONE_NEAR);
+        contract.assign_seat(NAME.to_string(), bob());
+
+        set_callback_context(NOW_MS, PromiseResult::Successful(vec![]));
+        contract.assign_seat_after_social_get(
+            Ok(get_badges(&contract, &[bob()])),
+            carol(),
+            NAME.to_string(),
+            bob(),
+        );
+        contract.unlock_accounts(vec![bob()]);
+        assert!(get_status(&contract, bob(), NOW_MS) == HolderStatus::Active);
+
+        set_context(carol(), NOW_MS + 1_000, 0);
+        contract.unassign_seat(NAME.to_string(), bob());
+
+        assert!(get_status(&contract, bob(), NOW_MS + 1_000) == HolderStatus::Expired);
+    }
+
+    // returns is_premium passed to the callback of the calling contract
+    fn check_premium_then(
+        contract: &mut SocialPremium,
+        account_id: AccountId,
+        now_ms: u128,
+    ) -> bool {
+        let gate_id = AccountId::new_unchecked("gate.near".to_string());
+        set_context(gate_id.clone(), now_ms, 0);
+        contract.check_premium_then(
+            account_id.clone(),
+            NAME.to_string(),
+            "on_premium_checked".to_string(),
+            None,
+            None,
+        );
+
+        let receipt = get_created_receipts()
+            .into_iter()
+            .find(|receipt| receipt.receiver_id == gate_id)
+            .expect("ERR_NO_CALLBACK");
+        let args = match &receipt.actions[0] {
+            VmAction::FunctionCall {
+                function_name,
+                args,
+                ..
+            } if function_name == "on_premium_checked" => {
+                near_sdk::serde_json::from_slice::<Value>(args).unwrap()
+            }
+            _ => panic!("ERR_NO_CALLBACK"),
+        };
+        assert_eq!(args["account_id"], account_id.to_string());
+        args["is_premium"].as_bool().unwrap()
+    }
+
+    #[test]
+    fn test_check_premium_then_active() {
+        let mut contract = setup_grace(None);
+        purchase(&mut contract, alice(), ONE_NEAR, None, NOW_MS);
+
+        assert!(check_premium_then(&mut contract, alice(), NOW_MS));
+    }
+
+    #[test]
+    fn test_check_premium_then_grace() {
+        let mut contract = setup_grace(None);
+        purchase(&mut contract, alice(), ONE_NEAR, None, NOW_MS);
+
+        let expires_at = NOW_MS + MONTH_IN_MS;
+        assert!(check_premium_then(&mut contract, alice(), expires_at));
+        assert!(!check_premium_then(
+            &mut contract,
+            alice(),
+            expires_at + GRACE_MS
+        ));
+    }
+
+    #[test]
+    fn test_check_premium_then_transferred_away() {
+        let mut contract = setup_grace(None);
+        purchase(&mut contract, alice(), ONE_NEAR, None, NOW_MS);
+        transfer(&mut contract, alice(), bob(), NOW_MS + 1_000);
+
+        assert!(!check_premium_then(&mut contract, alice(), NOW_MS + 1_000));
+        assert!(check_premium_then(&mut contract, bob(), NOW_MS + 1_000));
+    }
+
+    #[test]
+    fn test_check_premium_then_expired() {
+        let mut contract = setup_grace(None);
+
+        assert!(!check_premium_then(&mut contract, alice(), NOW_MS));
+    }
+}
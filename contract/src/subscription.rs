@@ -2,9 +2,43 @@ use crate::*;
 
 #[derive(BorshSerialize, BorshDeserialize)]
 pub enum VSubscription {
+    V1(SubscriptionV1),
+    V2(SubscriptionV2),
+    V3(SubscriptionV3),
     Current(Subscription),
 }
 
+#[derive(BorshSerialize, BorshDeserialize)]
+pub struct SubscriptionV1 {
+    pub title: String,
+    pub description: String,
+    pub image_url: String,
+    pub price: u128,
+    pub price_wholesale: u128,
+}
+
+#[derive(BorshSerialize, BorshDeserialize)]
+pub struct SubscriptionV2 {
+    pub title: String,
+    pub description: String,
+    pub image_url: String,
+    pub price: u128,
+    pub price_wholesale: u128,
+    pub kind: SubscriptionKind,
+}
+
+#[derive(BorshSerialize, BorshDeserialize)]
+pub struct SubscriptionV3 {
+    pub title: String,
+    pub description: String,
+    pub image_url: String,
+    pub price: u128,
+    pub price_wholesale: u128,
+    pub kind: SubscriptionKind,
+    pub trial_duration_ms: Option<u128>,
+    pub trial_deposit: Balance,
+}
+
 #[derive(BorshSerialize, BorshDeserialize)]
 pub struct Subscription {
     pub title: String,
@@ -12,11 +46,77 @@ pub struct Subscription {
     pub image_url: String,
     pub price: u128,
     pub price_wholesale: u128,
+    pub kind: SubscriptionKind,
+    // duration of the free trial, trials are disabled if None
+    pub trial_duration_ms: Option<u128>,
+    // refundable deposit required to start a trial
+    pub trial_deposit: Balance,
+    // time after the expiry when the holder is still premium and renews without a gap
+    pub grace_period_ms: u128,
+}
+
+#[derive(BorshSerialize, BorshDeserialize, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub enum SubscriptionKind {
+    Individual,
+    // bought by one payer and shared with up to max_members invited accounts
+    Shared { max_members: u32 },
+}
+
+impl From<SubscriptionV1> for Subscription {
+    fn from(subscription: SubscriptionV1) -> Self {
+        Subscription {
+            title: subscription.title,
+            description: subscription.description,
+            image_url: subscription.image_url,
+            price: subscription.price,
+            price_wholesale: subscription.price_wholesale,
+            kind: SubscriptionKind::Individual,
+            trial_duration_ms: None,
+            trial_deposit: 0,
+            grace_period_ms: 0,
+        }
+    }
+}
+
+impl From<SubscriptionV2> for Subscription {
+    fn from(subscription: SubscriptionV2) -> Self {
+        Subscription {
+            title: subscription.title,
+            description: subscription.description,
+            image_url: subscription.image_url,
+            price: subscription.price,
+            price_wholesale: subscription.price_wholesale,
+            kind: subscription.kind,
+            trial_duration_ms: None,
+            trial_deposit: 0,
+            grace_period_ms: 0,
+        }
+    }
+}
+
+impl From<SubscriptionV3> for Subscription {
+    fn from(subscription: SubscriptionV3) -> Self {
+        Subscription {
+            title: subscription.title,
+            description: subscription.description,
+            image_url: subscription.image_url,
+            price: subscription.price,
+            price_wholesale: subscription.price_wholesale,
+            kind: subscription.kind,
+            trial_duration_ms: subscription.trial_duration_ms,
+            trial_deposit: subscription.trial_deposit,
+            grace_period_ms: 0,
+        }
+    }
 }
 
 impl From<VSubscription> for Subscription {
     fn from(v_subscription: VSubscription) -> Self {
         match v_subscription {
+            VSubscription::V1(subscription) => subscription.into(),
+            VSubscription::V2(subscription) => subscription.into(),
+            VSubscription::V3(subscription) => subscription.into(),
             VSubscription::Current(subscription) => subscription,
         }
     }
@@ -30,18 +130,26 @@ pub struct SubscriptionOutput {
     pub image_url: String,
     pub price: U128,
     pub price_wholesale: U128,
+    pub kind: SubscriptionKind,
+    pub trial_duration_ms: Option<U128>,
+    pub trial_deposit: U128,
+    pub grace_period_ms: U128,
 }
 
 impl From<VSubscription> for SubscriptionOutput {
     fn from(v_subscription: VSubscription) -> Self {
-        match v_subscription {
-            VSubscription::Current(subscription) => SubscriptionOutput {
-                title: subscription.title,
-                description: subscription.description,
-                image_url: subscription.image_url,
-                price: U128::from(subscription.price),
-                price_wholesale: U128::from(subscription.price_wholesale),
-            },
+        let subscription = Subscription::from(v_subscription);
+
+        SubscriptionOutput {
+            title: subscription.title,
+            description: subscription.description,
+            image_url: subscription.image_url,
+            price: U128::from(subscription.price),
+            price_wholesale: U128::from(subscription.price_wholesale),
+            kind: subscription.kind,
+            trial_duration_ms: subscription.trial_duration_ms.map(U128::from),
+            trial_deposit: U128::from(subscription.trial_deposit),
+            grace_period_ms: U128::from(subscription.grace_period_ms),
         }
     }
 }
@@ -0,0 +1,155 @@
+use crate::*;
+
+use near_sdk::serde_json::{Map, Value};
+use near_sdk::test_utils::VMContextBuilder;
+use near_sdk::{testing_env, PromiseResult, RuntimeFeesConfig, VMConfig};
+
+pub const ONE_NEAR: Balance = 1_000_000_000_000_000_000_000_000;
+pub const NAME: &str = "premium";
+// the subscription price, so a purchase of 1 NEAR buys a month
+pub const PRICE: Balance = 12 * ONE_NEAR;
+pub const NOW_MS: u128 = 1_700_000_000_000;
+
+pub fn owner() -> AccountId {
+    AccountId::new_unchecked("owner.near".to_string())
+}
+
+pub fn alice() -> AccountId {
+    AccountId::new_unchecked("alice.near".to_string())
+}
+
+pub fn bob() -> AccountId {
+    AccountId::new_unchecked("bob.near".to_string())
+}
+
+pub fn carol() -> AccountId {
+    AccountId::new_unchecked("carol.near".to_string())
+}
+
+pub fn get_context(predecessor_id: AccountId, now_ms: u128) -> VMContextBuilder {
+    let mut context = VMContextBuilder::new();
+    context
+        .current_account_id(AccountId::new_unchecked(
+            SOCIAL_PREMIUM_ACCOUNT_ID.to_string(),
+        ))
+        .signer_account_id(predecessor_id.clone())
+        .predecessor_account_id(predecessor_id)
+        .account_balance(100 * ONE_NEAR)
+        .block_index(1_000)
+        .block_timestamp((now_ms * 1_000_000) as u64);
+    context
+}
+
+pub fn set_context(predecessor_id: AccountId, now_ms: u128, deposit: Balance) {
+    testing_env!(get_context(predecessor_id, now_ms)
+        .attached_deposit(deposit)
+        .build());
+}
+
+// sets the context of a callback with the result of the previous promise
+pub fn set_callback_context(now_ms: u128, promise_result: PromiseResult) {
+    let context = get_context(
+        AccountId::new_unchecked(SOCIAL_PREMIUM_ACCOUNT_ID.to_string()),
+        now_ms,
+    )
+    .build();
+    testing_env!(
+        context,
+        VMConfig::test(),
+        RuntimeFeesConfig::test(),
+        Default::default(),
+        vec![promise_result]
+    );
+}
+
+pub fn fee(numerator: u32, denominator: u32) -> FeeFraction {
+    FeeFraction {
+        numerator,
+        denominator,
+    }
+}
+
+// new deployment with a single subscription
+pub fn setup_contract(max_members: Option<u32>) -> SocialPremium {
+    set_context(owner(), NOW_MS, 0);
+
+    let mut contract = SocialPremium::new(owner(), fee(1, 10), fee(2, 10));
+    contract.add_subscription(
+        NAME.to_string(),
+        "Premium".to_string(),
+        "Premium subscription".to_string(),
+        "https://premium.near".to_string(),
+        U128::from(PRICE),
+        U128::from(100 * PRICE),
+        max_members,
+    );
+    contract
+}
+
+// SocialDB response with the badges the contract wrote for the accounts
+pub fn get_badges(contract: &SocialPremium, account_ids: &[AccountId]) -> Value {
+    let mut receiver_data: Map<String, Value> = Map::new();
+    for account_id in account_ids {
+        if let Some(expires_at) = contract
+            .holder_expiries
+            .get(&(NAME.to_string(), account_id.clone()))
+        {
+            receiver_data.insert(
+                account_id.to_string(),
+                Value::String(expires_at.to_string()),
+            );
+        }
+    }
+
+    if receiver_data.is_empty() {
+        return Value::Object(Map::new());
+    }
+
+    let mut data: Map<String, Value> = Map::new();
+    data.insert(
+        SOCIAL_PREMIUM_ACCOUNT_ID.to_string(),
+        Value::Object(get_badge_accounts_data(&NAME.to_string(), receiver_data)),
+    );
+    Value::Object(data)
+}
+
+pub fn purchase(
+    contract: &mut SocialPremium,
+    receiver_id: AccountId,
+    amount: Balance,
+    referral_account_id: Option<AccountId>,
+    now_ms: u128,
+) {
+    let mut account_ids = vec![receiver_id.clone()];
+    account_ids.extend(referral_account_id.clone());
+
+    set_callback_context(now_ms, PromiseResult::Successful(vec![]));
+    contract.purchase_after_social_get(
+        Ok(get_badges(contract, &account_ids)),
+        receiver_id.clone(),
+        NAME.to_string(),
+        U128::from(amount),
+        referral_account_id,
+        receiver_id,
+    );
+}
+
+pub fn transfer(
+    contract: &mut SocialPremium,
+    sender_id: AccountId,
+    receiver_id: AccountId,
+    now_ms: u128,
+) {
+    set_callback_context(now_ms, PromiseResult::Successful(vec![]));
+    contract.transfer_after_social_get(
+        Ok(get_badges(
+            contract,
+            &[sender_id.clone(), receiver_id.clone()],
+        )),
+        sender_id,
+        receiver_id,
+        NAME.to_string(),
+        None,
+        None,
+    );
+}
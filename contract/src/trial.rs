@@ -0,0 +1,291 @@
+use crate::*;
+
+use near_sdk::serde_json::Value;
+
+#[derive(BorshSerialize, BorshDeserialize)]
+pub struct TrialClaim {
+    pub expires_at: u128,
+    // refundable deposit left after the trial
+    pub deposit: Balance,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct TrialClaimOutput {
+    pub expires_at: U128,
+    pub deposit: U128,
+}
+
+#[near_bindgen]
+impl SocialPremium {
+    pub fn set_subscription_trial(
+        &mut self,
+        name: SubscriptionName,
+        trial_duration_ms: Option<U128>,
+        trial_deposit: Option<U128>,
+    ) {
+        self.assert_owner();
+
+        let mut subscription = self.internal_get_subscription(&name);
+        subscription.trial_duration_ms = trial_duration_ms.map(|duration| duration.0);
+        subscription.trial_deposit = trial_deposit.map(|deposit| deposit.0).unwrap_or(0);
+
+        self.subscriptions
+            .insert(&name, &VSubscription::Current(subscription));
+    }
+
+    #[payable]
+    pub fn start_trial(&mut self, name: SubscriptionName) -> Promise {
+        let account_id = env::predecessor_account_id();
+
+        let subscription = self.internal_get_subscription(&name);
+        assert!(
+            subscription.trial_duration_ms.is_some(),
+            "ERR_TRIAL_NOT_AVAILABLE"
+        );
+        self.assert_not_shared(&name);
+
+        let deposit = env::attached_deposit();
+        assert!(
+            deposit >= subscription.trial_deposit,
+            "Deposit {} required",
+            subscription.trial_deposit
+        );
+
+        assert!(
+            self.trial_claims
+                .get(&(account_id.clone(), name.clone()))
+                .is_none(),
+            "ERR_TRIAL_ALREADY_CLAIMED"
+        );
+        assert!(
+            !self.internal_has_held_subscription(&name, &account_id),
+            "ERR_TRIAL_NOT_AVAILABLE"
+        );
+
+        self.lock_account(&account_id);
+
+        let keys: Vec<String> = vec![format!(
+            "{}/badge/{}/accounts/{}",
+            SOCIAL_PREMIUM_ACCOUNT_ID, name, account_id
+        )];
+
+        ext_social::ext(AccountId::new_unchecked(SOCIAL_DB_ACCOUNT_ID.to_string()))
+            .with_static_gas(GAS_FOR_SOCIAL_GET)
+            .get(keys, None)
+            .then(
+                ext_self::ext(env::current_account_id())
+                    .with_static_gas(GAS_FOR_AFTER_SOCIAL_GET)
+                    .start_trial_after_social_get(account_id.clone(), name, U128::from(deposit)),
+            )
+            .then(
+                ext_self::ext(env::current_account_id())
+                    .with_static_gas(GAS_FOR_UNLOCK)
+                    .unlock_accounts(vec![account_id]),
+            )
+    }
+
+    #[private]
+    pub fn start_trial_after_social_get(
+        &mut self,
+        #[callback_result] value: Result<Value, PromiseError>,
+        account_id: AccountId,
+        subscription_name: SubscriptionName,
+        deposit: U128,
+    ) {
+        let key = (account_id.clone(), subscription_name.clone());
+
+        // the trial may be disabled while the badge is read
+        let trial_duration_ms = self
+            .internal_get_subscription(&subscription_name)
+            .trial_duration_ms;
+
+        // every check is done before the deposit is kept, so it is either stored
+        // in the trial claim or returned
+        let is_available = match (value.as_ref(), trial_duration_ms) {
+            (Ok(value), Some(_)) => {
+                // trials are available only for accounts which never held the subscription
+                let accounts = get_accounts_data(value, &subscription_name);
+                get_paid_until(accounts, &account_id).is_none()
+                    && self.trial_claims.get(&key).is_none()
+                    && !self.internal_has_held_subscription(&subscription_name, &account_id)
+            }
+            _ => false,
+        };
+
+        if !is_available {
+            log!("Trial is not available for {}", account_id);
+
+            if deposit.0 > 0 {
+                Promise::new(account_id).transfer(deposit.0);
+            }
+            return;
+        }
+
+        let now: u128 = env::block_timestamp_ms().into();
+        let expires_at = now + trial_duration_ms.unwrap_or_default();
+
+        self.trial_claims.insert(
+            &key,
+            &TrialClaim {
+                expires_at,
+                deposit: deposit.0,
+            },
+        );
+
+        log!("Trial started by {} until {}", account_id, expires_at);
+
+        self.operations += 1;
+        self.internal_add_history_receipt(
+            &account_id,
+            HistoryKind::Grant,
+            &subscription_name,
+            0,
+            expires_at - now,
+            expires_at,
+            None,
+            None,
+        );
+
+        self.internal_set_subscription_holder(
+            subscription_name,
+            vec![SubscriptionData {
+                receiver_id: account_id,
+                timestamp: expires_at,
+            }],
+        )
+        .as_return();
+    }
+
+    pub fn withdraw_trial_deposit(&mut self, name: SubscriptionName) -> Promise {
+        let account_id = env::predecessor_account_id();
+        let key = (account_id.clone(), name);
+
+        let mut claim = self.trial_claims.get(&key).expect("ERR_NO_TRIAL");
+        assert!(claim.deposit > 0, "ERR_NO_TRIAL_DEPOSIT");
+
+        let now: u128 = env::block_timestamp_ms().into();
+        assert!(claim.expires_at <= now, "ERR_TRIAL_IS_ACTIVE");
+
+        let deposit = claim.deposit;
+        claim.deposit = 0;
+        self.trial_claims.insert(&key, &claim);
+
+        Promise::new(account_id).transfer(deposit)
+    }
+
+    pub fn get_trial(
+        &self,
+        account_id: AccountId,
+        name: SubscriptionName,
+    ) -> Option<TrialClaimOutput> {
+        self.trial_claims
+            .get(&(account_id, name))
+            .map(|claim| TrialClaimOutput {
+                expires_at: U128::from(claim.expires_at),
+                deposit: U128::from(claim.deposit),
+            })
+    }
+}
+
+impl SocialPremium {
+    // trial time can't be moved to other accounts until the trial expires
+    pub(crate) fn assert_no_active_trial(&self, name: &SubscriptionName, account_id: &AccountId) {
+        let now: u128 = env::block_timestamp_ms().into();
+        assert!(
+            self.trial_claims
+                .get(&(account_id.clone(), name.clone()))
+                .map(|claim| claim.expires_at <= now)
+                .unwrap_or(true),
+            "ERR_TRIAL_IS_ACTIVE"
+        );
+    }
+
+    // checks the contract state, since the badge may be removed from the SocialDB
+    fn internal_has_held_subscription(
+        &self,
+        name: &SubscriptionName,
+        account_id: &AccountId,
+    ) -> bool {
+        self.holder_expiries
+            .get(&(name.clone(), account_id.clone()))
+            .is_some()
+            || self
+                .former_holders
+                .contains(&(name.clone(), account_id.clone()))
+            || self
+                .subscription_buyers
+                .contains(&(name.clone(), account_id.clone()))
+            || self
+                .account_history
+                .get(account_id)
+                .unwrap_or_default()
+                .iter()
+                .any(|receipt| &receipt.subscription_name == name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::*;
+    use near_sdk::mock::VmAction;
+    use near_sdk::test_utils::get_created_receipts;
+    use near_sdk::PromiseResult;
+
+    const TRIAL_DEPOSIT: Balance = ONE_NEAR / 10;
+
+    fn setup_trial() -> SocialPremium {
+        let mut contract = setup_contract(None);
+        contract.set_subscription_trial(
+            NAME.to_string(),
+            Some(U128::from(DAY_IN_MS)),
+            Some(U128::from(TRIAL_DEPOSIT)),
+        );
+        contract
+    }
+
+    fn start_trial_after_social_get(contract: &mut SocialPremium) {
+        set_callback_context(NOW_MS, PromiseResult::Successful(vec![]));
+        contract.start_trial_after_social_get(
+            Ok(get_badges(contract, &[alice()])),
+            alice(),
+            NAME.to_string(),
+            U128::from(TRIAL_DEPOSIT),
+        );
+    }
+
+    fn is_deposit_returned() -> bool {
+        get_created_receipts().iter().any(|receipt| {
+            receipt.receiver_id == alice()
+                && receipt.actions
+                    == vec![VmAction::Transfer {
+                        deposit: TRIAL_DEPOSIT,
+                    }]
+        })
+    }
+
+    #[test]
+    fn test_trial_on_empty_treasury() {
+        let mut contract = setup_trial();
+        assert_eq!(contract.get_ledger().available.0, 0);
+
+        start_trial_after_social_get(&mut contract);
+
+        let claim = contract.get_trial(alice(), NAME.to_string()).unwrap();
+        assert_eq!(claim.deposit.0, TRIAL_DEPOSIT);
+        assert_eq!(claim.expires_at.0, NOW_MS + DAY_IN_MS);
+        assert!(!is_deposit_returned());
+    }
+
+    #[test]
+    fn test_disabled_trial_returns_deposit() {
+        let mut contract = setup_trial();
+        contract.set_subscription_trial(NAME.to_string(), None, None);
+
+        start_trial_after_social_get(&mut contract);
+
+        assert!(contract.get_trial(alice(), NAME.to_string()).is_none());
+        assert!(is_deposit_returned());
+    }
+}
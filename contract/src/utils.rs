@@ -29,6 +29,39 @@ impl SocialPremium {
     }
 }
 
+// charges attached deposit for the storage used since initial_storage and refunds the rest
+pub fn charge_storage_deposit(initial_storage: StorageUsage) {
+    let required_deposit = get_storage_deposit(initial_storage);
+    let attached_deposit = env::attached_deposit();
+
+    assert!(
+        attached_deposit >= required_deposit,
+        "Deposit {} required to cover storage",
+        required_deposit
+    );
+
+    let refund = attached_deposit - required_deposit;
+    if refund > 1 {
+        Promise::new(env::predecessor_account_id()).transfer(refund);
+    }
+}
+
+// cost of the storage used since initial_storage
+pub fn get_storage_deposit(initial_storage: StorageUsage) -> Balance {
+    let storage_used = env::storage_usage().saturating_sub(initial_storage);
+    Balance::from(storage_used) * env::storage_byte_cost()
+}
+
+// returns the cost of the storage released since initial_storage to the given account
+pub fn refund_released_storage(initial_storage: StorageUsage, account_id: AccountId) {
+    let storage_released = initial_storage.saturating_sub(env::storage_usage());
+    let refund = Balance::from(storage_released) * env::storage_byte_cost();
+
+    if refund > 0 {
+        Promise::new(account_id).transfer(refund);
+    }
+}
+
 #[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone)]
 #[serde(crate = "near_sdk::serde")]
 pub struct FeeFraction {
@@ -45,6 +78,15 @@ impl FeeFraction {
         );
     }
 
+    pub fn assert_valid_sum(&self, other: &FeeFraction) {
+        assert!(
+            u128::from(self.numerator) * u128::from(other.denominator)
+                + u128::from(other.numerator) * u128::from(self.denominator)
+                <= u128::from(self.denominator) * u128::from(other.denominator),
+            "The sum of the fees must be less or equal to 1"
+        );
+    }
+
     pub fn multiply(&self, value: Balance) -> Balance {
         (U256::from(self.numerator) * U256::from(value) / U256::from(self.denominator)).as_u128()
     }